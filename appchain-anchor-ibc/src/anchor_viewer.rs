@@ -1,6 +1,7 @@
 use base64::Engine;
+use near_sdk::json_types::I64;
 
-use crate::*;
+use crate::{permissonless_actions::split_vsc_packet_data, voting_power::stake_to_voting_power, *};
 
 pub trait AnchorViewer {
     /// Get the chain id of corresponding appchain.
@@ -23,10 +24,98 @@ pub trait AnchorViewer {
     fn get_validator_set(&self, index: U64) -> Option<ValidatorSetView>;
     /// Get latest validator set history.
     fn get_latest_validator_set(&self) -> Option<ValidatorSetView>;
+    /// Get the changes of validators between two validator sets.
+    fn get_validator_set_diff(&self, from_id: U64, to_id: U64) -> Option<ValidatorSetDiffView>;
+    /// Get the target voting powers of the latest validator set, and the voting powers
+    /// which have been applied in appchain.
+    fn get_voting_powers(&self) -> Option<VotingPowersView>;
+    /// Get the status of the last fetching of validator set from restaking base contract.
+    fn get_last_fetch_status(&self) -> Option<ValidatorSetFetchStatus>;
+    /// Get the records of VSC packets sent for the given validator set.
+    fn get_vsc_packet_records_of(&self, validator_set_id: U64) -> Vec<VscPacketRecord>;
+    /// Get the validator sets which are not matured in appchain yet, from the latest one.
+    fn get_unmatured_validator_sets(&self) -> Vec<UnmaturedValidatorSetView>;
+    /// Get the delivery status of the last validator updates sent for the given validator set.
+    fn get_vsc_packet_delivery_of(&self, validator_set_id: U64) -> Option<VscPacketDeliveryView>;
+    /// Get the VSC packets which would be sent for the latest validator set
+    /// with the given removing pubkeys and slash acks.
+    fn preview_vsc_packet(
+        &self,
+        removing_pubkeys: Vec<String>,
+        slash_acks: Vec<String>,
+    ) -> Option<VscPacketPreview>;
     /// Get all registered addresses of validators.
     fn get_registered_addresses(&self) -> Vec<(String, String)>;
+    /// Get the operator account of the given validator.
+    fn get_operator_of(&self, validator_id: AccountId) -> Option<AccountId>;
+    /// Get the jail records of the given validator.
+    ///
+    /// Returns up to 50 records starting from `start_index`.
+    fn get_jail_records_of(
+        &self,
+        validator_id: AccountId,
+        start_index: Option<U64>,
+        quantity: Option<U64>,
+    ) -> Vec<JailRecord>;
+    /// Get index range of pending slash packets.
+    fn get_index_range_of_pending_slash_packets(&self) -> IndexRange;
     /// Get pending slash packets.
-    fn get_pending_slash_packets(&self) -> Vec<String>;
+    ///
+    /// Returns up to 50 records starting from `start_index`.
+    fn get_pending_slash_packets(
+        &self,
+        start_index: Option<U64>,
+        quantity: Option<U64>,
+    ) -> Vec<SlashPacketRecord>;
+    /// Get slash request by its slash id.
+    fn get_slash_request(&self, slash_id: U64) -> Option<SlashRequest>;
+    /// Get slash requests which are not rejected or executed yet.
+    ///
+    /// Returns up to 50 open slash requests starting from the `start_index`-th open one,
+    /// in the order of creation.
+    fn get_open_slash_requests(
+        &self,
+        start_index: Option<U64>,
+        quantity: Option<U64>,
+    ) -> Vec<SlashRequest>;
+    /// Get index range of slash requests which failed to be sent to restaking base contract.
+    fn get_index_range_of_failed_slash_requests(&self) -> IndexRange;
+    /// Get slash requests which failed to be sent to restaking base contract.
+    fn get_failed_slash_requests(
+        &self,
+        start_index: Option<U64>,
+        quantity: Option<U64>,
+    ) -> Vec<FailedSlashRequest>;
+    /// Get index range of slash histories.
+    fn get_index_range_of_slash_histories(&self) -> IndexRange;
+    /// Get slash histories.
+    ///
+    /// Returns up to 50 records starting from `start_index`.
+    fn get_slash_histories(
+        &self,
+        start_index: Option<U64>,
+        quantity: Option<U64>,
+    ) -> Vec<SlashHistoryRecord>;
+    /// Get slash histories of the given validator.
+    ///
+    /// Returns up to `limit` (at most 50) records, skipping the first `from` records
+    /// of the validator.
+    fn get_slash_history_of(
+        &self,
+        validator_id: AccountId,
+        from: U64,
+        limit: U64,
+    ) -> Vec<SlashHistoryRecord>;
+    /// Get index range of slash packets which can not be applied.
+    fn get_index_range_of_dead_letter_slash_packets(&self) -> IndexRange;
+    /// Get slash packets which can not be applied.
+    ///
+    /// Returns up to 50 records starting from `start_index`.
+    fn get_dead_letter_slash_packets(
+        &self,
+        start_index: Option<U64>,
+        quantity: Option<U64>,
+    ) -> Vec<DeadLetterSlashPacket>;
 }
 
 #[near_bindgen]
@@ -94,6 +183,192 @@ impl AnchorViewer for AppchainAnchor {
             .map(|vs| self.get_validator_set_view_of(&vs))
     }
     //
+    fn get_validator_set_diff(&self, from_id: U64, to_id: U64) -> Option<ValidatorSetDiffView> {
+        let from_vs = self.validator_set_histories.get(&from_id.0)?;
+        let to_vs = self.validator_set_histories.get(&to_id.0)?;
+        let from_powers = self.get_voting_powers_of(&from_vs).unwrap_or_default();
+        let to_powers = self.get_voting_powers_of(&to_vs).unwrap_or_default();
+        let power_of = |powers: &Vec<(AccountId, u64)>, validator_id: &AccountId| {
+            powers
+                .iter()
+                .find(|(id, _)| id == validator_id)
+                .map_or(0, |(_, power)| *power)
+        };
+        let (start_time, end_time) = match from_vs.timestamp() <= to_vs.timestamp() {
+            true => (from_vs.timestamp(), to_vs.timestamp()),
+            false => (to_vs.timestamp(), from_vs.timestamp()),
+        };
+        let mut validator_ids = from_vs.get_validator_ids();
+        to_vs.get_validator_ids().into_iter().for_each(|id| {
+            if !from_vs.contains_validator(&id) {
+                validator_ids.push(id);
+            }
+        });
+        let mut diff = ValidatorSetDiffView {
+            from_id,
+            to_id,
+            joined: Vec::new(),
+            left: Vec::new(),
+            stake_changed: Vec::new(),
+            status_changed: Vec::new(),
+            key_changed: Vec::new(),
+        };
+        for validator_id in validator_ids {
+            let old_validator = from_vs.get_validator(&validator_id);
+            let new_validator = to_vs.get_validator(&validator_id);
+            let old_voting_power = power_of(&from_powers, &validator_id);
+            let new_voting_power = power_of(&to_powers, &validator_id);
+            let change = ValidatorChangeView {
+                validator_id: validator_id.clone(),
+                old_total_stake: U128::from(old_validator.as_ref().map_or(0, |v| v.total_stake)),
+                new_total_stake: U128::from(new_validator.as_ref().map_or(0, |v| v.total_stake)),
+                old_status: old_validator.as_ref().map(|v| v.status.clone()),
+                new_status: new_validator.as_ref().map(|v| v.status.clone()),
+                old_voting_power: U64::from(old_voting_power),
+                new_voting_power: U64::from(new_voting_power),
+                voting_power_delta: I64::from(new_voting_power as i64 - old_voting_power as i64),
+            };
+            match (old_validator, new_validator) {
+                (None, Some(_)) => diff.joined.push(change),
+                (Some(_), None) => diff.left.push(change),
+                (Some(old_validator), Some(new_validator)) => {
+                    if old_validator.total_stake != new_validator.total_stake {
+                        diff.stake_changed.push(change.clone());
+                    }
+                    if old_validator.status != new_validator.status {
+                        diff.status_changed.push(change.clone());
+                    }
+                    if self
+                        .validator_key_change_timestamps
+                        .get(&validator_id)
+                        .is_some_and(|timestamps| {
+                            timestamps
+                                .iter()
+                                .any(|ts| *ts > start_time && *ts <= end_time)
+                        })
+                    {
+                        diff.key_changed.push(change);
+                    }
+                }
+                (None, None) => unreachable!(),
+            }
+        }
+        Some(diff)
+    }
+    //
+    fn get_voting_powers(&self) -> Option<VotingPowersView> {
+        let validator_set = self.validator_set_histories.get_last()?;
+        let mut target = self
+            .get_target_voting_powers_of(&validator_set)
+            .unwrap_or_default();
+        let mut applied = self.applied_voting_powers.to_vec();
+        target.sort();
+        applied.sort();
+        let to_view = |(public_key, power): &(Vec<u8>, u64)| ValidatorPowerView {
+            validator_id: self
+                .validator_address_to_id_map
+                .get(&calculate_address(public_key)),
+            public_key: format!(
+                "ed25519:{}",
+                base64::engine::general_purpose::STANDARD.encode(public_key)
+            ),
+            voting_power: U64::from(*power),
+        };
+        Some(VotingPowersView {
+            validator_set_id: U64::from(validator_set.id()),
+            target: target.iter().map(to_view).collect(),
+            applied: applied.iter().map(to_view).collect(),
+            fully_applied: target == applied,
+        })
+    }
+    //
+    fn get_last_fetch_status(&self) -> Option<ValidatorSetFetchStatus> {
+        self.last_fetch_status.clone()
+    }
+    //
+    fn get_vsc_packet_records_of(&self, validator_set_id: U64) -> Vec<VscPacketRecord> {
+        (0..self.get_vsc_packet_record_count_of(&validator_set_id))
+            .filter_map(|index| self.vsc_packet_records.get(&(validator_set_id.0, index)))
+            .collect()
+    }
+    //
+    fn get_unmatured_validator_sets(&self) -> Vec<UnmaturedValidatorSetView> {
+        let index_range = self.validator_set_histories.index_range();
+        let mut result = Vec::new();
+        for index in (index_range.start_index.0..index_range.end_index.0 + 1).rev() {
+            match self.validator_set_histories.get(&index) {
+                Some(validator_set) if !validator_set.matured_in_appchain() => {
+                    result.push(UnmaturedValidatorSetView {
+                        validator_set_id: U64::from(validator_set.id()),
+                        sequence: U64::from(validator_set.sequence()),
+                        timestamp: validator_set.timestamp(),
+                        age: U64::from(
+                            env::block_timestamp().saturating_sub(validator_set.timestamp()),
+                        ),
+                        delivered: self.is_validator_set_delivered(&U64::from(index)),
+                    })
+                }
+                _ => break,
+            }
+        }
+        result
+    }
+    //
+    fn get_vsc_packet_delivery_of(&self, validator_set_id: U64) -> Option<VscPacketDeliveryView> {
+        let (chunk_count, outstanding_chunks) =
+            self.get_outstanding_vsc_packet_chunks_of(&validator_set_id)?;
+        Some(VscPacketDeliveryView {
+            validator_set_id,
+            chunk_count,
+            delivered: outstanding_chunks.is_empty(),
+            outstanding_chunks,
+        })
+    }
+    //
+    fn preview_vsc_packet(
+        &self,
+        removing_pubkeys: Vec<String>,
+        slash_acks: Vec<String>,
+    ) -> Option<VscPacketPreview> {
+        let validator_set = self.validator_set_histories.get_last()?;
+        let removing_pubkeys = removing_pubkeys
+            .iter()
+            .map(|rp| {
+                decode_ed25519_pubkey(rp)
+                    .unwrap_or_else(|_| panic!("Invalid removing pubkey: {}", rp))
+            })
+            .collect();
+        let packet_data =
+            self.generate_vsc_packet_data(&validator_set, &removing_pubkeys, &slash_acks);
+        let anchor_settings = self.anchor_settings.get().unwrap();
+        let hrp = anchor_settings.appchain_address_bech32_hrp;
+        let validator_pubkeys = packet_data
+            .validator_pubkeys
+            .iter()
+            .map(|vkp| {
+                let address = calculate_address(&vkp.public_key);
+                ValidatorKeyAndPowerView {
+                    validator_id: self.validator_address_to_id_map.get(&address),
+                    public_key_hex: hex::encode(&vkp.public_key),
+                    public_key_base64: format!(
+                        "ed25519:{}",
+                        base64::engine::general_purpose::STANDARD.encode(&vkp.public_key)
+                    ),
+                    address_in_appchain: calculate_bech32_address(hrp.clone(), address),
+                    power: vkp.power,
+                }
+            })
+            .collect();
+        Some(VscPacketPreview {
+            packets: split_vsc_packet_data(
+                packet_data,
+                anchor_settings.max_validator_updates_per_vsc_packet,
+            ),
+            validator_pubkeys,
+            held_back: self.is_held_back(&validator_set),
+        })
+    }
+    //
     fn get_registered_addresses(&self) -> Vec<(String, String)> {
         self.validator_address_to_id_map
             .iter()
@@ -106,8 +381,117 @@ impl AnchorViewer for AppchainAnchor {
             .collect()
     }
     //
-    fn get_pending_slash_packets(&self) -> Vec<String> {
-        self.pending_slash_packets.to_vec()
+    fn get_operator_of(&self, validator_id: AccountId) -> Option<AccountId> {
+        self.validator_operators.get(&validator_id)
+    }
+    //
+    fn get_jail_records_of(
+        &self,
+        validator_id: AccountId,
+        start_index: Option<U64>,
+        quantity: Option<U64>,
+    ) -> Vec<JailRecord> {
+        self.jail_ledger.get_records_of(
+            &validator_id,
+            start_index.map_or(0, |index| index.0),
+            quantity.map_or(50, |quantity| quantity.0.min(50)),
+        )
+    }
+    //
+    fn get_index_range_of_pending_slash_packets(&self) -> IndexRange {
+        self.pending_slash_packets.index_range()
+    }
+    //
+    fn get_pending_slash_packets(
+        &self,
+        start_index: Option<U64>,
+        quantity: Option<U64>,
+    ) -> Vec<SlashPacketRecord> {
+        self.pending_slash_packets.get_slice_of(
+            &start_index.map_or(0, |index| index.0),
+            quantity.map(|quantity| quantity.0),
+        )
+    }
+    //
+    fn get_slash_request(&self, slash_id: U64) -> Option<SlashRequest> {
+        self.slash_requests.get(&slash_id.0)
+    }
+    //
+    fn get_open_slash_requests(
+        &self,
+        start_index: Option<U64>,
+        quantity: Option<U64>,
+    ) -> Vec<SlashRequest> {
+        self.slash_requests
+            .values_as_vector()
+            .iter()
+            .filter(|slash_request| slash_request.is_open())
+            .skip(start_index.map_or(0, |index| index.0) as usize)
+            .take(quantity.map_or(50, |quantity| quantity.0.min(50)) as usize)
+            .collect()
+    }
+    //
+    fn get_index_range_of_failed_slash_requests(&self) -> IndexRange {
+        self.failed_slash_requests.index_range()
+    }
+    //
+    fn get_failed_slash_requests(
+        &self,
+        start_index: Option<U64>,
+        quantity: Option<U64>,
+    ) -> Vec<FailedSlashRequest> {
+        self.failed_slash_requests.get_slice_of(
+            &start_index.map_or(0, |index| index.0),
+            quantity.map(|quantity| quantity.0),
+        )
+    }
+    //
+    fn get_index_range_of_slash_histories(&self) -> IndexRange {
+        self.slash_histories.index_range()
+    }
+    //
+    fn get_slash_histories(
+        &self,
+        start_index: Option<U64>,
+        quantity: Option<U64>,
+    ) -> Vec<SlashHistoryRecord> {
+        self.slash_histories.get_slice_of(
+            &start_index.map_or(0, |index| index.0),
+            quantity.map(|quantity| quantity.0),
+        )
+    }
+    //
+    fn get_slash_history_of(
+        &self,
+        validator_id: AccountId,
+        from: U64,
+        limit: U64,
+    ) -> Vec<SlashHistoryRecord> {
+        self.slash_history_indexes_of_validator
+            .get(&validator_id)
+            .map_or(vec![], |indexes| {
+                indexes
+                    .iter()
+                    .skip(from.0 as usize)
+                    .take(limit.0.min(50) as usize)
+                    .filter_map(|index| self.slash_histories.get(index))
+                    .collect()
+            })
+    }
+    //
+    fn get_index_range_of_dead_letter_slash_packets(&self) -> IndexRange {
+        self.dead_letter_slash_packets.index_range()
+    }
+    //
+    fn get_dead_letter_slash_packets(
+        &self,
+        start_index: Option<U64>,
+        quantity: Option<U64>,
+    ) -> Vec<DeadLetterSlashPacket> {
+        self.dead_letter_slash_packets.get_slice_of(
+            &start_index.map_or(0, |index| index.0),
+            quantity.map(|quantity| quantity.0),
+        )
     }
 }
 
@@ -115,17 +499,35 @@ impl AppchainAnchor {
     //
     fn get_validator_set_view_of(&self, validator_set: &ValidatorSet) -> ValidatorSetView {
         let anchor_settings = self.anchor_settings.get().unwrap();
-        ValidatorSetView {
+        let period_end = self
+            .validator_set_histories
+            .get(&(validator_set.id() + 1))
+            .map_or(u64::MAX, |next_vs| next_vs.timestamp());
+        let voting_powers = self.get_voting_powers_of(validator_set).unwrap_or_default();
+        let mut validator_set_view = ValidatorSetView {
             id: U64::from(validator_set.id()),
             validators: validator_set
                 .get_validator_ids()
                 .iter()
                 .map(|id| {
                     if let Some(validator) = validator_set.get_validator(&id) {
+                        let jail_term = self.jail_ledger.get_jail_term(id, &anchor_settings);
                         ValidatorView {
                             validator_id: validator.validator_id,
                             total_stake: validator.total_stake.into(),
-                            voting_power: U64::from((validator.total_stake / NEAR_SCALE) as u64),
+                            voting_power: U64::from(
+                                stake_to_voting_power(
+                                    validator.total_stake,
+                                    anchor_settings.voting_power_divisor.0,
+                                )
+                                .unwrap_or(0),
+                            ),
+                            effective_voting_power: U64::from(
+                                voting_powers
+                                    .iter()
+                                    .find(|(validator_id, _)| validator_id == id)
+                                    .map_or(0, |(_, power)| *power),
+                            ),
                             status: validator.status,
                             registered_pubkey: self.validator_id_to_pubkey_map.get(&id).map_or(
                                 String::new(),
@@ -145,6 +547,9 @@ impl AppchainAnchor {
                                     )
                                 },
                             ),
+                            jail_term: jail_term.map(|(_, jail_term)| U64::from(jail_term)),
+                            unjailable_timestamp: jail_term
+                                .map(|(jailed_time, jail_term)| U64::from(jailed_time + jail_term)),
                         }
                     } else {
                         unreachable!()
@@ -155,7 +560,30 @@ impl AppchainAnchor {
             sequence: U64::from(validator_set.sequence()),
             timestamp: validator_set.timestamp(),
             matured_on_appchain: validator_set.matured_in_appchain(),
-            jailed_validators: validator_set.jailed_validators(),
-        }
+            matured_timestamp: validator_set.matured_timestamp(),
+            jailed_validators: validator_set
+                .get_validator_ids()
+                .iter()
+                .flat_map(|id| {
+                    self.jail_ledger
+                        .get_records_in_period(id, validator_set.timestamp(), period_end)
+                        .into_iter()
+                        .map(|record| {
+                            (
+                                id.clone(),
+                                record.jailed_timestamp,
+                                record.unjailed_timestamp,
+                            )
+                        })
+                })
+                .collect(),
+        };
+        validator_set_view.validators.sort_by(|a, b| {
+            b.total_stake
+                .0
+                .cmp(&a.total_stake.0)
+                .then_with(|| a.validator_id.cmp(&b.validator_id))
+        });
+        validator_set_view
     }
 }
@@ -1,4 +1,5 @@
 mod near_ibc_actions;
+pub mod near_ibc_callbacks;
 mod restaking_base_actions;
 pub mod restaking_base_callbacks;
 pub mod reward_token_callbacks;
@@ -9,6 +9,15 @@ pub trait NearIbcActions {
     fn on_vsc_matured(&mut self, validator_set_id: U64);
     /// Interface for near-ibc to call when distribute_reward packet is received.
     fn distribute_reward(&mut self, validator_set_id: U64);
+    /// Interface for near-ibc to call when the acknowledgement of a VSC packet is received.
+    fn on_vsc_packet_acknowledged(
+        &mut self,
+        validator_set_id: U64,
+        sequence: U64,
+        error: Option<String>,
+    );
+    /// Interface for near-ibc to call when a VSC packet is timed out.
+    fn on_vsc_packet_timeout(&mut self, validator_set_id: U64, sequence: U64);
 }
 
 #[near_bindgen]
@@ -16,24 +25,72 @@ impl NearIbcActions for AppchainAnchor {
     /// Interface for near-ibc to call when slash packet is received.
     fn slash_validator(&mut self, slach_packet_data: SlashPacketData) {
         self.assert_near_ibc_contract();
+        self.assert_state_migrated();
         let slash_packet_view = SlashPacketView {
             validator: slach_packet_data.validator,
             valset_update_id: slach_packet_data.valset_update_id,
             infraction: slach_packet_data.infraction,
             received_timestamp: env::block_timestamp(),
         };
-        let mut packet_string = serde_json::to_string(&slash_packet_view).unwrap();
-        self.pending_slash_packets.append(&mut packet_string);
+        let mut slash_packet = SlashPacketRecord::from_view(&slash_packet_view);
+        let dedup_key = slash_packet.dedup_key();
+        if let Some((index, duplicate_count)) = self.received_slash_packets.get(&dedup_key) {
+            if self.is_slash_packet_in_effect(index, &slash_packet) {
+                self.received_slash_packets
+                    .insert(&dedup_key, &(index, duplicate_count + 1));
+                emit_nep297_event("SLASH_PACKET_DUPLICATED", &slash_packet_view);
+                return;
+            }
+        }
+        self.pending_slash_packets.append(&mut slash_packet);
+        self.received_slash_packets
+            .insert(&dedup_key, &(slash_packet.index.0, 0));
         emit_nep297_event("SLASH_PACKET_RECEIVED", &slash_packet_view);
     }
     /// Interface for near-ibc to call when vsc_matured packet is received.
+    ///
+    /// The earlier validator sets which are not matured yet are also marked as matured,
+    /// as the maturity of a validator set implies the maturity of earlier ones.
     fn on_vsc_matured(&mut self, validator_set_id: U64) {
         self.assert_near_ibc_contract();
-        if let Some(mut validator_set) = self.validator_set_histories.get(&validator_set_id.0) {
+        self.assert_state_migrated();
+        if self
+            .validator_set_histories
+            .get(&validator_set_id.0)
+            .is_none()
+        {
+            log!(
+                "VSC matured packet for unknown validator set {}.",
+                validator_set_id.0
+            );
+            emit_nep297_event(
+                "VSC_MATURED_FOR_UNKNOWN_VALIDATOR_SET",
+                &json!({ "validator_set_id": validator_set_id }),
+            );
+            return;
+        }
+        let start_index = self.validator_set_histories.index_range().start_index.0;
+        let mut matured_validator_set_ids = Vec::new();
+        let mut index = validator_set_id.0;
+        while let Some(mut validator_set) = self.validator_set_histories.get(&index) {
+            if validator_set.matured_in_appchain() {
+                break;
+            }
             validator_set.set_matured();
-            self.validator_set_histories
-                .update(&validator_set_id.0, &validator_set);
+            self.validator_set_histories.update(&index, &validator_set);
+            matured_validator_set_ids.push(U64::from(index));
+            if index <= start_index {
+                break;
+            }
+            index -= 1;
         }
+        emit_nep297_event(
+            "VSC_MATURED",
+            &json!({
+                "validator_set_id": validator_set_id,
+                "matured_validator_set_ids": matured_validator_set_ids,
+            }),
+        );
     }
     /// Interface for near-ibc to call when distribute_reward packet is received.
     fn distribute_reward(&mut self, validator_set_id: U64) {
@@ -52,4 +109,229 @@ impl NearIbcActions for AppchainAnchor {
             reward_distribution
         );
     }
+    /// Interface for near-ibc to call when the acknowledgement of a VSC packet is received.
+    fn on_vsc_packet_acknowledged(
+        &mut self,
+        validator_set_id: U64,
+        sequence: U64,
+        error: Option<String>,
+    ) {
+        self.assert_near_ibc_contract();
+        let state = match &error {
+            Some(error) => VscPacketState::AckError(error.clone()),
+            None => VscPacketState::Acknowledged,
+        };
+        let was_delivered = self.is_validator_set_delivered(&validator_set_id);
+        let (_, record) = match self.update_vsc_packet_state(&validator_set_id, &sequence, state) {
+            Some(result) => result,
+            None => {
+                log!(
+                    "Unknown VSC packet with sequence {} for validator set {}.",
+                    sequence.0,
+                    validator_set_id.0
+                );
+                return;
+            }
+        };
+        if let Some(error) = error {
+            emit_nep297_event(
+                "VSC_PACKET_ACK_ERROR",
+                &json!({
+                    "validator_set_id": validator_set_id,
+                    "sequence": sequence,
+                    "error": error,
+                }),
+            );
+        } else {
+            self.apply_voting_powers_in(&record.packet_data);
+            if !was_delivered && self.is_validator_set_delivered(&validator_set_id) {
+                emit_nep297_event(
+                    "VALIDATOR_SET_DELIVERED",
+                    &json!({ "validator_set_id": validator_set_id }),
+                );
+            }
+        }
+    }
+    /// Interface for near-ibc to call when a VSC packet is timed out.
+    fn on_vsc_packet_timeout(&mut self, validator_set_id: U64, sequence: U64) {
+        self.assert_near_ibc_contract();
+        let record_index = match self.update_vsc_packet_state(
+            &validator_set_id,
+            &sequence,
+            VscPacketState::TimedOut,
+        ) {
+            Some((record_index, _)) => record_index,
+            None => {
+                log!(
+                    "Unknown VSC packet with sequence {} for validator set {}.",
+                    sequence.0,
+                    validator_set_id.0
+                );
+                return;
+            }
+        };
+        let policy = self
+            .anchor_settings
+            .get()
+            .unwrap()
+            .vsc_packet_timeout_policy;
+        emit_nep297_event(
+            "VSC_PACKET_TIMEOUT",
+            &json!({
+                "validator_set_id": validator_set_id,
+                "sequence": sequence,
+                "policy": policy,
+            }),
+        );
+        if self.appchain_state != AppchainState::Active {
+            return;
+        }
+        match policy {
+            VscPacketTimeoutPolicy::Resend => {
+                self.resend_vsc_packet(&validator_set_id, record_index)
+            }
+            VscPacketTimeoutPolicy::CloseAppchain => {
+                self.appchain_state = AppchainState::Closing;
+                self.sync_state_to_registry();
+                emit_nep297_event(
+                    "APPCHAIN_CLOSING",
+                    &json!({
+                        "reason": format!(
+                            "VSC packet with sequence {} for validator set {} is timed out.",
+                            sequence.0, validator_set_id.0
+                        ),
+                    }),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::{test_utils::VMContextBuilder, testing_env};
+    use octopus_lpos::packet::consumer::Validator;
+
+    fn setup_contract() -> AppchainAnchor {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id("appchain.registry.testnet".parse().unwrap())
+            .build());
+        let contract = AppchainAnchor::new(
+            "restaking-base.testnet".parse().unwrap(),
+            "lpos-market.testnet".parse().unwrap(),
+            "near-ibc.testnet".parse().unwrap(),
+            "reward-token.testnet".parse().unwrap(),
+        );
+        testing_env!(VMContextBuilder::new()
+            .current_account_id("appchain.registry.testnet".parse().unwrap())
+            .predecessor_account_id("near-ibc.testnet".parse().unwrap())
+            .build());
+        contract
+    }
+
+    fn downtime_slash_packet() -> SlashPacketData {
+        SlashPacketData {
+            validator: Some(Validator {
+                address: vec![0; 20],
+                power: 100,
+            }),
+            valset_update_id: 0,
+            infraction: "INFRACTION_DOWNTIME".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_slash_validator_with_duplicated_packets() {
+        let mut contract = setup_contract();
+        let validator0: AccountId = "validator0.testnet".parse().unwrap();
+        let mut validator_set = ValidatorSet::new(&None, 0);
+        validator_set.add_validator(validator0.clone(), 100, ValidatorStatus::Active);
+        contract.validator_set_histories.append(&mut validator_set);
+        contract
+            .validator_address_to_id_map
+            .insert(&vec![0; 20], &validator0);
+        contract.slash_validator(downtime_slash_packet());
+        assert_eq!(contract.pending_slash_packets.len(), 1);
+        let dedup_key = contract
+            .pending_slash_packets
+            .get_first()
+            .unwrap()
+            .dedup_key();
+        // The duplicated packet is ignored while the first one is still pending.
+        contract.slash_validator(downtime_slash_packet());
+        assert_eq!(contract.pending_slash_packets.len(), 1);
+        assert_eq!(
+            contract.received_slash_packets.get(&dedup_key),
+            Some((0, 1))
+        );
+        // The duplicated packet is ignored while the validator is still jailed.
+        contract
+            .pending_slash_packets
+            .remove_first(Gas::from_tgas(10));
+        let mut latest_vs = contract.validator_set_histories.get_last().unwrap();
+        latest_vs.jail_validator(&validator0, &mut contract.jail_ledger);
+        contract.validator_set_histories.update_last(&latest_vs);
+        contract.slash_validator(downtime_slash_packet());
+        assert_eq!(contract.pending_slash_packets.len(), 0);
+        assert_eq!(
+            contract.received_slash_packets.get(&dedup_key),
+            Some((0, 2))
+        );
+        // The same packet is accepted again once the validator is active in the latest
+        // validator set.
+        let mut validator_set = ValidatorSet::new(&Some(latest_vs), 1);
+        validator_set.add_validator(validator0.clone(), 100, ValidatorStatus::Active);
+        contract.validator_set_histories.append(&mut validator_set);
+        contract.slash_validator(downtime_slash_packet());
+        assert_eq!(contract.pending_slash_packets.len(), 1);
+        assert_eq!(
+            contract.received_slash_packets.get(&dedup_key),
+            Some((0, 0))
+        );
+    }
+
+    #[test]
+    fn test_on_vsc_matured_marks_earlier_validator_sets() {
+        let mut contract = setup_contract();
+        let mut last_vs = None;
+        for sequence in 0..4 {
+            let mut validator_set = ValidatorSet::new(&last_vs, sequence);
+            contract.validator_set_histories.append(&mut validator_set);
+            last_vs = Some(validator_set);
+        }
+        let matured_ids = |contract: &AppchainAnchor| -> Vec<u64> {
+            (0..4)
+                .filter(|index| {
+                    contract
+                        .validator_set_histories
+                        .get(index)
+                        .unwrap()
+                        .matured_in_appchain()
+                })
+                .collect()
+        };
+        contract.on_vsc_matured(U64::from(1));
+        assert_eq!(matured_ids(&contract), vec![0, 1]);
+        // The propagation stops at the first matured validator set.
+        testing_env!(VMContextBuilder::new()
+            .current_account_id("appchain.registry.testnet".parse().unwrap())
+            .predecessor_account_id("near-ibc.testnet".parse().unwrap())
+            .block_timestamp(100)
+            .build());
+        contract.on_vsc_matured(U64::from(3));
+        assert_eq!(matured_ids(&contract), vec![0, 1, 2, 3]);
+        let matured_timestamp_of = |index: u64| {
+            contract
+                .validator_set_histories
+                .get(&index)
+                .unwrap()
+                .matured_timestamp()
+        };
+        assert_eq!(matured_timestamp_of(1), Some(0));
+        assert_eq!(matured_timestamp_of(2), Some(100));
+        // The maturity of an unknown validator set is ignored.
+        contract.on_vsc_matured(U64::from(5));
+        assert_eq!(matured_ids(&contract), vec![0, 1, 2, 3]);
+    }
 }
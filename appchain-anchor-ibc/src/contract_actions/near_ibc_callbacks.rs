@@ -0,0 +1,58 @@
+use crate::*;
+use near_sdk::PromiseResult;
+
+#[ext_contract(ext_near_ibc_callbacks)]
+pub trait NearIbcCallbacks {
+    /// Callback function for `send_vsc_packet` of near-ibc contract
+    fn send_vsc_packet_callback(&mut self, validator_set_id: U64, record_index: u32);
+}
+
+#[near_bindgen]
+impl NearIbcCallbacks for AppchainAnchor {
+    //
+    fn send_vsc_packet_callback(&mut self, validator_set_id: U64, record_index: u32) {
+        near_sdk::assert_self();
+        let mut record = match self
+            .vsc_packet_records
+            .get(&(validator_set_id.0, record_index))
+        {
+            Some(record) if record.state == VscPacketState::Sending => record,
+            _ => return,
+        };
+        match env::promise_result(0) {
+            PromiseResult::Successful(value) => {
+                record.sequence = near_sdk::serde_json::from_slice::<U64>(&value).ok();
+                record.state = match record.sequence {
+                    Some(_) => VscPacketState::Sent,
+                    None => {
+                        //
+                        // The near-ibc contract which does not return the sequence will not
+                        // report the acknowledgement either, so the voting powers are applied
+                        // now, otherwise they never advance.
+                        //
+                        log!(
+                            "No sequence of VSC packet for validator set {}, it is regarded as applied.",
+                            validator_set_id.0
+                        );
+                        self.apply_voting_powers_in(&record.packet_data);
+                        VscPacketState::SentWithoutSequence
+                    }
+                };
+            }
+            PromiseResult::Failed => {
+                log!(
+                    "Failed to send VSC packet for validator set {}.",
+                    validator_set_id.0
+                );
+                record.state = VscPacketState::SendFailed;
+                emit_nep297_event(
+                    "VSC_PACKET_SEND_FAILED",
+                    &json!({ "validator_set_id": validator_set_id }),
+                );
+            }
+        }
+        record.updated_timestamp = env::block_timestamp();
+        self.vsc_packet_records
+            .insert(&(validator_set_id.0, record_index), &record);
+    }
+}
@@ -12,6 +12,14 @@ pub trait RestakingBaseActions {
     /// The `key` param should be in base64 format like
     /// `ed25519:GMaw7UPsXqPr7IRijvt/BgVU93A6hs98JZbUJtKMAuA=`.
     fn change_key(&mut self, staker_id: AccountId, key: String);
+    /// Set the operator account of the validator, which can act on behalf of the validator.
+    ///
+    /// The operator will be removed if `operator_id` is `None`.
+    fn set_operator(&mut self, staker_id: AccountId, operator_id: Option<AccountId>);
+    /// Report that a slash request is executed in restaking base contract.
+    fn on_slash_request_executed(&mut self, slash_id: U64);
+    /// Report that a slash request is cancelled in restaking base contract.
+    fn on_slash_request_cancelled(&mut self, slash_id: U64);
 }
 
 #[near_bindgen]
@@ -44,8 +52,68 @@ impl RestakingBaseActions for AppchainAnchor {
                     .remove(&calculate_address(old_key.as_slice()));
                 self.validator_address_to_id_map
                     .insert(&calculate_address(public_key.as_slice()), &staker_id);
+                let mut key_change_timestamps = self
+                    .validator_key_change_timestamps
+                    .get(&staker_id)
+                    .unwrap_or_default();
+                key_change_timestamps.push(env::block_timestamp());
+                self.validator_key_change_timestamps
+                    .insert(&staker_id, &key_change_timestamps);
             }
             Err(err) => panic!("Invalid public key: {:?}", err),
         };
     }
+    //
+    fn set_operator(&mut self, staker_id: AccountId, operator_id: Option<AccountId>) {
+        self.assert_restaking_base_contract();
+        match operator_id {
+            Some(operator_id) => {
+                self.validator_operators.insert(&staker_id, &operator_id);
+            }
+            None => {
+                self.validator_operators.remove(&staker_id);
+            }
+        }
+    }
+    //
+    fn on_slash_request_executed(&mut self, slash_id: U64) {
+        self.assert_restaking_base_contract();
+        let slash_request = self
+            .slash_requests
+            .get(&slash_id.0)
+            .expect(format!("Slash request {} is not found.", slash_id.0).as_str());
+        assert!(
+            slash_request.state != SlashRequestState::Executed,
+            "Slash request {} is already executed.",
+            slash_id.0
+        );
+        if slash_request.state == SlashRequestState::Rejected {
+            log!(
+                "Slash request {} is executed by restaking base contract after being rejected.",
+                slash_id.0
+            );
+        }
+        self.update_slash_request_state(&slash_id, SlashRequestState::Executed);
+    }
+    //
+    fn on_slash_request_cancelled(&mut self, slash_id: U64) {
+        self.assert_restaking_base_contract();
+        let slash_request = self
+            .slash_requests
+            .get(&slash_id.0)
+            .expect(format!("Slash request {} is not found.", slash_id.0).as_str());
+        if slash_request.state == SlashRequestState::Rejected {
+            log!(
+                "Slash request {} is already vetoed by the owner of anchor.",
+                slash_id.0
+            );
+            return;
+        }
+        assert!(
+            slash_request.is_open(),
+            "Slash request {} is already closed.",
+            slash_id.0
+        );
+        self.update_slash_request_state(&slash_id, SlashRequestState::Rejected);
+    }
 }
@@ -1,4 +1,4 @@
-use crate::{ext_contracts::RestakingBaseValidatorSet, *};
+use crate::{ext_contracts::RestakingBaseValidatorSet, validator_set::ValidatorStatus, *};
 use near_sdk::PromiseResult;
 
 #[ext_contract(ext_restaking_base_callbacks)]
@@ -6,7 +6,15 @@ pub trait RestakingBaseCallbacks {
     /// Callback function for `get_validator_set` of restaking base contract
     fn get_validator_set_callback(&mut self);
     /// Callback function for `slash_request` of restaking base contract
-    fn slash_request_callback(&mut self, slash_items: Vec<(AccountId, U128)>);
+    fn slash_request_callback(
+        &mut self,
+        slash_packet: SlashPacketRecord,
+        slash_items: Vec<(AccountId, U128)>,
+        slash_history_index: U64,
+        retry_count: u32,
+    );
+    /// Callback function for `cancel_slash_request` of restaking base contract
+    fn cancel_slash_request_callback(&mut self, slash_id: U64);
 }
 
 #[near_bindgen]
@@ -14,26 +22,193 @@ impl RestakingBaseCallbacks for AppchainAnchor {
     //
     fn get_validator_set_callback(&mut self) {
         near_sdk::assert_self();
+        //
+        // Release the lock first, and do not panic in the following steps,
+        // otherwise the lock will not be released.
+        //
+        self.validator_set_fetch_in_flight = false;
         match env::promise_result(0) {
             PromiseResult::Successful(value) => {
                 let restaking_base_vs =
-                    near_sdk::serde_json::from_slice::<RestakingBaseValidatorSet>(&value).unwrap();
-                self.validator_set_histories
-                    .append(&mut self.generate_new_validator_set(restaking_base_vs));
+                    match near_sdk::serde_json::from_slice::<RestakingBaseValidatorSet>(&value) {
+                        Ok(restaking_base_vs) => restaking_base_vs,
+                        Err(err) => {
+                            self.record_fetch_failure(format!(
+                                "Invalid validator set from restaking base contract: {}",
+                                err
+                            ));
+                            return;
+                        }
+                    };
+                if let Some(last_vs) = self.validator_set_histories.get_last() {
+                    if restaking_base_vs.sequence.0 == last_vs.sequence()
+                        && last_vs.has_same_validators_as(&restaking_base_vs)
+                    {
+                        self.last_fetch_status = Some(ValidatorSetFetchStatus {
+                            sequence: Some(restaking_base_vs.sequence),
+                            timestamp: env::block_timestamp(),
+                            result: ValidatorSetFetchResult::Unchanged {
+                                validator_set_id: U64::from(last_vs.id()),
+                            },
+                        });
+                        emit_nep297_event(
+                            "VALIDATOR_SET_UNCHANGED",
+                            &json!({
+                                "sequence": restaking_base_vs.sequence,
+                                "validator_set_id": U64::from(last_vs.id()),
+                            }),
+                        );
+                        return;
+                    }
+                    if restaking_base_vs.sequence.0 <= last_vs.sequence() {
+                        self.reject_validator_set(
+                            restaking_base_vs.sequence,
+                            format!(
+                                "The sequence is not greater than the sequence {} of the latest validator set.",
+                                last_vs.sequence()
+                            ),
+                        );
+                        return;
+                    }
+                }
+                if self.validator_set_builder.is_some() {
+                    self.reject_validator_set(
+                        restaking_base_vs.sequence,
+                        "Another validator set is being built.".to_string(),
+                    );
+                    return;
+                }
+                let builder = ValidatorSetBuilder::new(
+                    &self.validator_set_histories.get_last(),
+                    &restaking_base_vs,
+                );
+                self.last_fetch_status = Some(ValidatorSetFetchStatus {
+                    sequence: Some(restaking_base_vs.sequence),
+                    timestamp: env::block_timestamp(),
+                    result: ValidatorSetFetchResult::Building {
+                        validator_set_id: U64::from(builder.validator_set().id()),
+                    },
+                });
+                self.validator_set_builder.set(&builder);
             }
             PromiseResult::Failed => {
-                log!("Failed to get validator set from restaking base contract.");
+                self.record_fetch_failure(
+                    "Failed to get validator set from restaking base contract.".to_string(),
+                );
             }
         }
     }
     //
-    fn slash_request_callback(&mut self, slash_items: Vec<(AccountId, U128)>) {
+    fn slash_request_callback(
+        &mut self,
+        slash_packet: SlashPacketRecord,
+        slash_items: Vec<(AccountId, U128)>,
+        slash_history_index: U64,
+        retry_count: u32,
+    ) {
         near_sdk::assert_self();
         match env::promise_result(0) {
             PromiseResult::Successful(value) => {
-                let slash_id = near_sdk::serde_json::from_slice::<U64>(&value).unwrap();
+                let slash_id = match near_sdk::serde_json::from_slice::<U64>(&value) {
+                    Ok(slash_id) => slash_id,
+                    Err(err) => {
+                        let reason = format!(
+                            "Invalid slash id returned by restaking base contract: {}",
+                            err
+                        );
+                        log!("{}", reason);
+                        self.update_slash_history(
+                            &slash_history_index,
+                            SlashAction::Rejected(reason.clone()),
+                            None,
+                        );
+                        let mut dead_letter = DeadLetterSlashPacket {
+                            index: U64::from(0),
+                            slash_packet,
+                            reason,
+                            timestamp: env::block_timestamp(),
+                        };
+                        self.dead_letter_slash_packets.append(&mut dead_letter);
+                        emit_nep297_event("SLASH_PACKET_REJECTED", &dead_letter);
+                        return;
+                    }
+                };
+                //
+                // Record the status change in the validator set referred by the slash packet.
+                //
+                let last_vs_id = self.validator_set_histories.index_range().end_index.0;
+                if slash_packet.valset_update_id.0 < last_vs_id {
+                    if let Some(mut referred_vs) = self
+                        .validator_set_histories
+                        .get(&slash_packet.valset_update_id.0)
+                    {
+                        for (validator_id, _) in &slash_items {
+                            referred_vs.wait_for_slashing_validator(validator_id, slash_id);
+                        }
+                        self.validator_set_histories
+                            .update(&slash_packet.valset_update_id.0, &referred_vs);
+                    }
+                }
+                //
+                // Apply the status change to the latest validator set, which will be used
+                // to generate the following VSC packets.
+                //
                 let mut latest_vs = self.validator_set_histories.get_last().unwrap();
-                latest_vs.wait_for_slashing_validator(&slash_items[0].0, slash_id);
+                let mut removing_pubkeys = Vec::new();
+                for (validator_id, amount) in &slash_items {
+                    removing_pubkeys.extend(self.get_removing_pubkeys_for_slashing(
+                        &latest_vs,
+                        validator_id,
+                        &slash_packet,
+                    ));
+                    latest_vs.wait_for_slashing_validator(validator_id, slash_id);
+                    emit_nep297_event(
+                        "VALIDATOR_WAIT_FOR_SLASH",
+                        &json!({
+                            "slash_id": slash_id,
+                            "validator_id": validator_id,
+                            "amount": amount,
+                            "valset_update_id": slash_packet.valset_update_id,
+                        }),
+                    );
+                }
+                let validator_ids: Vec<AccountId> = slash_items
+                    .iter()
+                    .map(|(validator_id, _)| validator_id.clone())
+                    .collect();
+                self.update_latest_validator_set(&latest_vs, &validator_ids);
+                let slash_request = SlashRequest {
+                    slash_id,
+                    validator_set_id: slash_packet.valset_update_id,
+                    slash_items: slash_items.clone(),
+                    evidence_sha256_hash: slash_packet.evidence_sha256_hash(),
+                    state: SlashRequestState::Requested,
+                    requested_timestamp: env::block_timestamp(),
+                    updated_timestamp: env::block_timestamp(),
+                };
+                self.slash_requests.insert(&slash_id.0, &slash_request);
+                emit_nep297_event("SLASH_REQUEST_CREATED", &slash_request);
+                self.update_slash_history(
+                    &slash_history_index,
+                    SlashAction::SlashRequested,
+                    Some(slash_id),
+                );
+                //
+                // Queue the removing pubkeys and the slash ack, which will be sent in
+                // the next VSC packet together with the others.
+                //
+                for pubkey in removing_pubkeys {
+                    if !self.pending_removing_pubkeys.contains(&pubkey) {
+                        self.pending_removing_pubkeys.push(pubkey);
+                    }
+                }
+                if let Some(address) = slash_packet.validator_address {
+                    let anchor_settings = self.anchor_settings.get().unwrap();
+                    self.pending_slash_acks.push(calculate_bech32_address(
+                        anchor_settings.appchain_address_bech32_hrp,
+                        address,
+                    ));
+                }
                 log!(
                     "Slash request for {:?} is sent to restaking base contract.",
                     slash_items
@@ -44,27 +219,148 @@ impl RestakingBaseCallbacks for AppchainAnchor {
                     "Failed to send slash request for {:?} to restaking base contract.",
                     slash_items
                 );
+                if retry_count >= MAX_RETRY_COUNT_OF_SLASH_REQUEST {
+                    let reason =
+                        format!("Slash request still failed after {} retries.", retry_count);
+                    self.update_slash_history(
+                        &slash_history_index,
+                        SlashAction::Rejected(reason.clone()),
+                        None,
+                    );
+                    let mut dead_letter = DeadLetterSlashPacket {
+                        index: U64::from(0),
+                        slash_packet,
+                        reason,
+                        timestamp: env::block_timestamp(),
+                    };
+                    self.dead_letter_slash_packets.append(&mut dead_letter);
+                    emit_nep297_event("SLASH_PACKET_REJECTED", &dead_letter);
+                    return;
+                }
+                self.update_slash_history(
+                    &slash_history_index,
+                    SlashAction::SlashRequestFailed,
+                    None,
+                );
+                let mut failed_slash_request = FailedSlashRequest {
+                    slash_packet,
+                    slash_items,
+                    slash_history_index,
+                    failed_timestamp: env::block_timestamp(),
+                    retry_count,
+                };
+                self.failed_slash_requests.append(&mut failed_slash_request);
+                emit_nep297_event("SLASH_REQUEST_FAILED", &failed_slash_request);
+            }
+        }
+    }
+    //
+    fn cancel_slash_request_callback(&mut self, slash_id: U64) {
+        near_sdk::assert_self();
+        match env::promise_result(0) {
+            PromiseResult::Successful(_) => {
+                log!(
+                    "Slash request {} is cancelled in restaking base contract.",
+                    slash_id.0
+                );
+            }
+            PromiseResult::Failed => {
+                log!(
+                    "Failed to cancel slash request {} in restaking base contract.",
+                    slash_id.0
+                );
+                emit_nep297_event(
+                    "SLASH_REQUEST_CANCEL_FAILED",
+                    &json!({ "slash_id": slash_id }),
+                );
             }
         }
     }
 }
 
 impl AppchainAnchor {
-    fn generate_new_validator_set(
-        &self,
-        restaking_base_vs: RestakingBaseValidatorSet,
-    ) -> ValidatorSet {
+    /// Accept the completely built validator set as the latest validator set.
+    pub fn complete_validator_set(&mut self, builder: ValidatorSetBuilder) {
         let anchor_settings = self.anchor_settings.get().unwrap();
-        let validator_set = ValidatorSet::new(
-            &self.validator_set_histories.get_last(),
-            &restaking_base_vs,
-            anchor_settings.min_validator_staking_amount.0,
+        let (mut validator_set, auto_unjailed_validators) = builder.into_parts();
+        let sequence = U64::from(validator_set.sequence());
+        for validator_id in auto_unjailed_validators {
+            //
+            // The jail record may be closed or removed, or the jail term may be changed,
+            // while the validator set is being built. So check it again here.
+            //
+            if self.jail_ledger.get_open_record(&validator_id).is_none() {
+                continue;
+            }
+            if !self
+                .jail_ledger
+                .is_unjailable(&validator_id, &anchor_settings)
+            {
+                if let Some(mut validator) = validator_set.get_validator(&validator_id) {
+                    validator.status = ValidatorStatus::Jailed;
+                    validator_set.validators.insert(&validator_id, &validator);
+                }
+                log!(
+                    "Validator '{}' is kept jailed in validator set {}.",
+                    validator_id,
+                    validator_set.id()
+                );
+                continue;
+            }
+            self.jail_ledger.unjail(&validator_id, &anchor_settings);
+            emit_nep297_event(
+                "VALIDATOR_AUTO_UNJAILED",
+                &json!({
+                    "validator_id": validator_id,
+                    "validator_set_id": U64::from(validator_set.id()),
+                }),
+            );
+        }
+        let held_back = self.is_held_back(&validator_set);
+        if held_back {
+            log!(
+                "Validator set with sequence '{}' is held back from appchain, as it has too few validators.",
+                sequence.0
+            );
+        }
+        self.validator_set_histories.append(&mut validator_set);
+        self.last_fetch_status = Some(ValidatorSetFetchStatus {
+            sequence: Some(sequence),
+            timestamp: env::block_timestamp(),
+            result: ValidatorSetFetchResult::Created {
+                validator_set_id: U64::from(validator_set.id()),
+                held_back,
+            },
+        });
+    }
+    //
+    pub fn reject_validator_set(&mut self, sequence: U64, reason: String) {
+        self.last_fetch_status = Some(ValidatorSetFetchStatus {
+            sequence: Some(sequence),
+            timestamp: env::block_timestamp(),
+            result: ValidatorSetFetchResult::Rejected(reason.clone()),
+        });
+        log!(
+            "Validator set with sequence '{}' is rejected: {}",
+            sequence.0,
+            reason
         );
-        assert!(
-            validator_set.active_validators(None).len() > 0,
-            "No qualified validator in new validator set with sequence '{}'.",
-            validator_set.sequence()
+        emit_nep297_event(
+            "VALIDATOR_SET_REJECTED",
+            &json!({
+                "sequence": sequence,
+                "reason": reason,
+            }),
         );
-        validator_set
+    }
+    //
+    fn record_fetch_failure(&mut self, reason: String) {
+        self.last_fetch_status = Some(ValidatorSetFetchStatus {
+            sequence: None,
+            timestamp: env::block_timestamp(),
+            result: ValidatorSetFetchResult::Failed(reason.clone()),
+        });
+        log!("{}", reason);
+        emit_nep297_event("VALIDATOR_SET_FETCH_FAILED", &json!({ "reason": reason }));
     }
 }
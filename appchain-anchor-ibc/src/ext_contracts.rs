@@ -33,6 +33,8 @@ pub trait RestakingBaseContract {
         slash_items: Vec<(AccountId, U128)>,
         evidence_sha256_hash: String,
     ) -> U64;
+    /// Cancel a slash request which is not executed yet.
+    fn cancel_slash_request(&mut self, slash_id: U64);
 }
 
 #[ext_contract(ext_near_ibc)]
@@ -51,10 +53,15 @@ pub trait NearIbcContract {
     );
     /// Start sending vsc packet from `near-ibc` contract to the appchain
     /// corresponding to this contract.
+    ///
+    /// Returns the IBC sequence of the sent packet.
+    ///
+    /// The `chunk` of the packet data only exists if the validator updates are split into
+    /// multiple packets, and must be carried to the appchain in the sent packet.
     fn send_vsc_packet(
         &mut self,
         chain_id: ChainId,
         vsc_packet_data: VscPacketData,
         timeout_timestamp_interval: U64,
-    );
+    ) -> U64;
 }
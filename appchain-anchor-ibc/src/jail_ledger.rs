@@ -0,0 +1,366 @@
+use crate::*;
+use near_sdk::Timestamp;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub struct JailRecord {
+    /// The timestamp of when the validator is jailed.
+    pub jailed_timestamp: Timestamp,
+    /// The timestamp of when the validator is unjailed, zero if it is still jailed.
+    pub unjailed_timestamp: Timestamp,
+}
+
+impl JailRecord {
+    ///
+    pub fn is_open(&self) -> bool {
+        self.unjailed_timestamp == 0
+    }
+    ///
+    pub fn overlaps(&self, start: Timestamp, end: Timestamp) -> bool {
+        self.jailed_timestamp <= end && (self.is_open() || self.unjailed_timestamp >= start)
+    }
+}
+
+/// The jail records of validators, which are kept across validator sets.
+///
+/// The records of a validator are appended in time order and never overlap,
+/// so the queries by time can be done by binary search.
+#[derive(BorshDeserialize, BorshSerialize)]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct JailLedger {
+    /// The count of jail records, mapped by the account id of validators.
+    record_counts: LookupMap<AccountId, u64>,
+    /// The jail records, mapped by the account id of validators and the index of records.
+    records: LookupMap<(AccountId, u64), JailRecord>,
+}
+
+impl JailLedger {
+    ///
+    pub fn new() -> Self {
+        Self {
+            record_counts: LookupMap::new(StorageKey::JailRecordCounts),
+            records: LookupMap::new(StorageKey::JailRecords),
+        }
+    }
+    ///
+    pub fn record_count_of(&self, validator_id: &AccountId) -> u64 {
+        self.record_counts.get(validator_id).unwrap_or(0)
+    }
+    ///
+    pub fn get_record(&self, validator_id: &AccountId, index: u64) -> Option<JailRecord> {
+        self.records.get(&(validator_id.clone(), index))
+    }
+    ///
+    pub fn get_records_of(
+        &self,
+        validator_id: &AccountId,
+        start_index: u64,
+        quantity: u64,
+    ) -> Vec<JailRecord> {
+        let end_index = self
+            .record_count_of(validator_id)
+            .min(start_index.saturating_add(quantity));
+        (start_index..end_index)
+            .filter_map(|index| self.get_record(validator_id, index))
+            .collect()
+    }
+    /// Get the jail record of the validator which is not unjailed yet.
+    pub fn get_open_record(&self, validator_id: &AccountId) -> Option<JailRecord> {
+        match self.record_count_of(validator_id) {
+            0 => None,
+            count => self
+                .get_record(validator_id, count - 1)
+                .filter(|record| record.is_open()),
+        }
+    }
+    /// Append a jail record of the validator.
+    ///
+    /// The record must not be earlier than the existing records of the validator.
+    pub fn append_record(&mut self, validator_id: &AccountId, record: &JailRecord) {
+        let count = self.record_count_of(validator_id);
+        if count > 0 {
+            let last_record = self.get_record(validator_id, count - 1).unwrap();
+            assert!(
+                !last_record.is_open() && last_record.unjailed_timestamp <= record.jailed_timestamp,
+                "Jail record of validator {} overlaps with the existing ones.",
+                validator_id
+            );
+        }
+        self.records.insert(&(validator_id.clone(), count), record);
+        self.record_counts.insert(validator_id, &(count + 1));
+    }
+    ///
+    pub fn jail(&mut self, validator_id: &AccountId) {
+        if self.get_open_record(validator_id).is_some() {
+            panic!("Validator already jailed: {}", validator_id);
+        }
+        self.append_record(
+            validator_id,
+            &JailRecord {
+                jailed_timestamp: env::block_timestamp(),
+                unjailed_timestamp: 0,
+            },
+        );
+    }
+    ///
+    pub fn unjail(&mut self, validator_id: &AccountId, anchor_settings: &AnchorSettings) {
+        let (jailed_time, jail_term) = self
+            .get_jail_term(validator_id, anchor_settings)
+            .unwrap_or_else(|| panic!("Validator not found: {}", validator_id));
+        if jailed_time + jail_term > env::block_timestamp() {
+            panic!("Validator is not jailed for long enough: {}", validator_id);
+        }
+        let index = self.record_count_of(validator_id) - 1;
+        self.records.insert(
+            &(validator_id.clone(), index),
+            &JailRecord {
+                jailed_timestamp: jailed_time,
+                unjailed_timestamp: env::block_timestamp(),
+            },
+        );
+    }
+    /// Whether the validator can be unjailed at current time.
+    pub fn is_unjailable(
+        &self,
+        validator_id: &AccountId,
+        anchor_settings: &AnchorSettings,
+    ) -> bool {
+        self.get_jail_term(validator_id, anchor_settings)
+            .is_some_and(|(jailed_time, jail_term)| {
+                jailed_time + jail_term <= env::block_timestamp()
+            })
+    }
+    /// Get the jailed time and the required jail term of the given validator,
+    /// if the validator is jailed.
+    ///
+    /// The jail term is `min_unjail_interval` doubled for each previous offence
+    /// within `jail_escalation_window`, and is capped at `max_unjail_interval`.
+    pub fn get_jail_term(
+        &self,
+        validator_id: &AccountId,
+        anchor_settings: &AnchorSettings,
+    ) -> Option<(Timestamp, u64)> {
+        let jailed_time = self.get_open_record(validator_id)?.jailed_timestamp;
+        let escalation_window = anchor_settings.jail_escalation_window.0;
+        let previous_offences = match escalation_window {
+            0 => 0,
+            _ => {
+                let window_start = jailed_time.saturating_sub(escalation_window);
+                let first_index = self.partition_point(validator_id, |record| {
+                    record.jailed_timestamp < window_start
+                });
+                (self.record_count_of(validator_id) - 1 - first_index) as u32
+            }
+        };
+        let min_unjail_interval = anchor_settings.min_unjail_interval.0;
+        let jail_term = min_unjail_interval
+            .saturating_mul(1u64.checked_shl(previous_offences).unwrap_or(u64::MAX))
+            .min(
+                anchor_settings
+                    .max_unjail_interval
+                    .0
+                    .max(min_unjail_interval),
+            );
+        Some((jailed_time, jail_term))
+    }
+    /// Whether the validator is jailed at any time in the given period.
+    pub fn is_jailed_in_period(
+        &self,
+        validator_id: &AccountId,
+        start: Timestamp,
+        end: Timestamp,
+    ) -> bool {
+        let index = self.partition_point(validator_id, |record| record.jailed_timestamp <= end);
+        index > 0
+            && self
+                .get_record(validator_id, index - 1)
+                .is_some_and(|record| record.overlaps(start, end))
+    }
+    /// Get the jail records of the validator which overlap with the given period.
+    pub fn get_records_in_period(
+        &self,
+        validator_id: &AccountId,
+        start: Timestamp,
+        end: Timestamp,
+    ) -> Vec<JailRecord> {
+        let mut records = Vec::new();
+        let mut index = self.partition_point(validator_id, |record| record.jailed_timestamp <= end);
+        while index > 0 {
+            index -= 1;
+            match self.get_record(validator_id, index) {
+                Some(record) if record.overlaps(start, end) => records.push(record),
+                _ => break,
+            }
+        }
+        records.reverse();
+        records
+    }
+    /// Remove the jail records of the validator, from the latest one.
+    pub fn clear_records_of(&mut self, validator_id: &AccountId, max_gas: Gas) -> ProcessingResult {
+        let mut count = self.record_count_of(validator_id);
+        while count > 0 {
+            if env::used_gas() > max_gas {
+                self.record_counts.insert(validator_id, &count);
+                return ProcessingResult::NeedMoreGas;
+            }
+            count -= 1;
+            self.records.remove(&(validator_id.clone(), count));
+        }
+        self.record_counts.remove(validator_id);
+        ProcessingResult::Ok
+    }
+    /// Get the index of the first record of the validator which does not satisfy the predicate,
+    /// assuming all records satisfying the predicate come first.
+    fn partition_point<P>(&self, validator_id: &AccountId, predicate: P) -> u64
+    where
+        P: Fn(&JailRecord) -> bool,
+    {
+        let (mut low, mut high) = (0, self.record_count_of(validator_id));
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match self.get_record(validator_id, mid) {
+                Some(record) if predicate(&record) => low = mid + 1,
+                _ => high = mid,
+            }
+        }
+        low
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::{test_utils::VMContextBuilder, testing_env};
+
+    fn set_block_timestamp(timestamp: Timestamp) {
+        testing_env!(VMContextBuilder::new().block_timestamp(timestamp).build());
+    }
+
+    fn jailed_timestamps(records: Vec<JailRecord>) -> Vec<Timestamp> {
+        records
+            .into_iter()
+            .map(|record| record.jailed_timestamp)
+            .collect()
+    }
+
+    #[test]
+    fn test_jail_records_in_period() {
+        set_block_timestamp(0);
+        let validator_id: AccountId = "alice.testnet".parse().unwrap();
+        let anchor_settings = AnchorSettings {
+            min_unjail_interval: U64::from(100),
+            ..Default::default()
+        };
+        let mut jail_ledger = JailLedger::new();
+        // Jailed in [100, 200], [300, 400] and since 500.
+        for (jailed_time, unjailed_time) in [(100, 200), (300, 400)] {
+            set_block_timestamp(jailed_time);
+            jail_ledger.jail(&validator_id);
+            set_block_timestamp(unjailed_time);
+            jail_ledger.unjail(&validator_id, &anchor_settings);
+        }
+        set_block_timestamp(500);
+        jail_ledger.jail(&validator_id);
+        assert_eq!(jail_ledger.record_count_of(&validator_id), 3);
+        assert_eq!(
+            jail_ledger
+                .get_open_record(&validator_id)
+                .map(|record| record.jailed_timestamp),
+            Some(500)
+        );
+        // Binary search over the records.
+        let jailed_before = |time: Timestamp| {
+            jail_ledger.partition_point(&validator_id, |record| record.jailed_timestamp <= time)
+        };
+        assert_eq!(jailed_before(50), 0);
+        assert_eq!(jailed_before(100), 1);
+        assert_eq!(jailed_before(350), 2);
+        assert_eq!(jailed_before(1000), 3);
+        // Overlap queries.
+        assert!(!jail_ledger.is_jailed_in_period(&validator_id, 0, 99));
+        assert!(jail_ledger.is_jailed_in_period(&validator_id, 150, 160));
+        assert!(jail_ledger.is_jailed_in_period(&validator_id, 50, 100));
+        assert!(!jail_ledger.is_jailed_in_period(&validator_id, 210, 290));
+        assert!(jail_ledger.is_jailed_in_period(&validator_id, 400, 450));
+        assert!(!jail_ledger.is_jailed_in_period(&validator_id, 401, 499));
+        assert!(jail_ledger.is_jailed_in_period(&validator_id, 600, 700));
+        assert_eq!(
+            jailed_timestamps(jail_ledger.get_records_in_period(&validator_id, 150, 350)),
+            vec![100, 300]
+        );
+        assert_eq!(
+            jailed_timestamps(jail_ledger.get_records_in_period(&validator_id, 0, 1000)),
+            vec![100, 300, 500]
+        );
+        assert!(jail_ledger
+            .get_records_in_period(&validator_id, 210, 290)
+            .is_empty());
+        // The records of other validators are not affected.
+        let other_validator_id: AccountId = "bob.testnet".parse().unwrap();
+        assert!(!jail_ledger.is_jailed_in_period(&other_validator_id, 0, 1000));
+    }
+
+    #[test]
+    fn test_jail_term_escalation() {
+        set_block_timestamp(0);
+        let validator_id: AccountId = "alice.testnet".parse().unwrap();
+        let anchor_settings = AnchorSettings {
+            min_unjail_interval: U64::from(100),
+            max_unjail_interval: U64::from(1000),
+            jail_escalation_window: U64::from(1000),
+            ..Default::default()
+        };
+        let mut jail_ledger = JailLedger::new();
+        assert_eq!(
+            jail_ledger.get_jail_term(&validator_id, &anchor_settings),
+            None
+        );
+        // The jail term is doubled for each previous offence in the window.
+        for (jailed_time, jail_term) in [(0, 100), (200, 200), (500, 400)] {
+            set_block_timestamp(jailed_time);
+            jail_ledger.jail(&validator_id);
+            assert_eq!(
+                jail_ledger.get_jail_term(&validator_id, &anchor_settings),
+                Some((jailed_time, jail_term))
+            );
+            set_block_timestamp(jailed_time + jail_term - 1);
+            assert!(!jail_ledger.is_unjailable(&validator_id, &anchor_settings));
+            set_block_timestamp(jailed_time + jail_term);
+            assert!(jail_ledger.is_unjailable(&validator_id, &anchor_settings));
+            jail_ledger.unjail(&validator_id, &anchor_settings);
+        }
+        // The jail term is capped by the max unjail interval.
+        set_block_timestamp(1000);
+        jail_ledger.jail(&validator_id);
+        assert_eq!(
+            jail_ledger.get_jail_term(
+                &validator_id,
+                &AnchorSettings {
+                    max_unjail_interval: U64::from(500),
+                    ..anchor_settings.clone()
+                }
+            ),
+            Some((1000, 500))
+        );
+        set_block_timestamp(1800);
+        jail_ledger.unjail(&validator_id, &anchor_settings);
+        // The offences out of the window are not counted.
+        set_block_timestamp(2100);
+        jail_ledger.jail(&validator_id);
+        assert_eq!(
+            jail_ledger.get_jail_term(&validator_id, &anchor_settings),
+            Some((2100, 100))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Validator already jailed")]
+    fn test_jail_jailed_validator() {
+        set_block_timestamp(0);
+        let validator_id: AccountId = "alice.testnet".parse().unwrap();
+        let mut jail_ledger = JailLedger::new();
+        jail_ledger.jail(&validator_id);
+        jail_ledger.jail(&validator_id);
+    }
+}
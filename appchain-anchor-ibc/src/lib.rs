@@ -15,6 +15,7 @@ use crate::prelude::*;
 use base64::{DecodeError, Engine};
 use bech32::ToBase32;
 use ibc::core::host::types::identifiers::ChainId;
+use jail_ledger::{JailLedger, JailRecord};
 use lookup_array::{IndexedAndClearable, LookupArray};
 use near_contract_standards::fungible_token::Balance;
 use near_sdk::{
@@ -28,12 +29,14 @@ use near_sdk::{
     PromiseOrValue,
 };
 use serde_json::json;
+use storage_migration::StateMigrationProgress;
 use types::*;
-use validator_set::{ValidatorSet, ValidatorSetViewer};
+use validator_set::{ValidatorSet, ValidatorSetBuilder, ValidatorSetViewer};
 
 mod anchor_viewer;
 mod contract_actions;
 mod ext_contracts;
+mod jail_ledger;
 pub mod lookup_array;
 mod permissonless_actions;
 mod prelude;
@@ -42,6 +45,7 @@ pub mod types;
 mod upgrade;
 mod user_actions;
 mod validator_set;
+mod voting_power;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 /// Constants for gas.
@@ -49,6 +53,9 @@ const T_GAS_FOR_SIMPLE_FUNCTION_CALL: u64 = 10;
 const T_GAS_CAP_FOR_MULTI_TXS_PROCESSING: u64 = 130;
 /// The scale for converting between `NEAR` and `yoctoNear`.
 const NEAR_SCALE: u128 = 1_000_000_000_000_000_000_000_000;
+/// The maximum number of retries of a failed slash request, after which the slash packet
+/// is moved to the dead-letter queue.
+const MAX_RETRY_COUNT_OF_SLASH_REQUEST: u32 = 5;
 
 /// Storage keys for collections of sub-struct in main contract
 #[derive(BorshDeserialize, BorshSerialize, BorshStorageKey, Clone)]
@@ -64,6 +71,22 @@ pub enum StorageKey {
     ValidatorIdSetOf(u64),
     ValidatorsOf(u64),
     PendingSlashPackets,
+    SlashRequests,
+    FailedSlashRequests,
+    DeadLetterSlashPackets,
+    ReceivedSlashPackets,
+    SlashHistories,
+    SlashHistoryIndexesOfValidator,
+    ValidatorOperators,
+    JailRecordCounts,
+    JailRecords,
+    ValidatorKeyChangeTimestamps,
+    AppliedVotingPowers,
+    ValidatorSetBuilder,
+    VscPacketRecords,
+    VscPacketRecordCounts,
+    StateMigrationProgress,
+    CollectedJailRecords,
 }
 
 #[near_bindgen]
@@ -99,7 +122,49 @@ pub struct AppchainAnchor {
     /// The pending rewards of validators which are not distributed yet.
     pending_rewards: LookupArray<RewardDistribution>,
     /// The pending slash packets received from near-ibc contract.
-    pending_slash_packets: LookupArray<String>,
+    pending_slash_packets: LookupArray<SlashPacketRecord>,
+    /// The slash requests sent to restaking base contract, mapped by their slash id.
+    slash_requests: UnorderedMap<u64, SlashRequest>,
+    /// The slash requests which failed to be sent to restaking base contract.
+    failed_slash_requests: LookupArray<FailedSlashRequest>,
+    /// The slash packets which can not be applied.
+    dead_letter_slash_packets: LookupArray<DeadLetterSlashPacket>,
+    /// The index of the first received slash packet in pending slash packets and
+    /// the number of its duplicates, mapped by their dedup key.
+    received_slash_packets: LookupMap<Vec<u8>, (u64, u32)>,
+    /// The histories of processed slash packets.
+    slash_histories: LookupArray<SlashHistoryRecord>,
+    /// The indexes of slash histories, mapped by the account id of validators.
+    slash_history_indexes_of_validator: LookupMap<AccountId, Vec<u64>>,
+    /// The operator accounts of validators, mapped by the account id of validators.
+    validator_operators: LookupMap<AccountId, AccountId>,
+    /// The jail records of validators.
+    jail_ledger: JailLedger,
+    /// The timestamps of key changes of validators, mapped by the account id of validators.
+    validator_key_change_timestamps: LookupMap<AccountId, Vec<u64>>,
+    /// The voting powers which have been applied in appchain, mapped by the public keys
+    /// of validators.
+    applied_voting_powers: UnorderedMap<Vec<u8>, u64>,
+    /// Whether a validator set is being fetched from restaking base contract.
+    validator_set_fetch_in_flight: bool,
+    /// The status of the last fetching of validator set from restaking base contract.
+    last_fetch_status: Option<ValidatorSetFetchStatus>,
+    /// The validator set which is being built from the validator set in restaking base contract.
+    validator_set_builder: LazyOption<ValidatorSetBuilder>,
+    /// The records of VSC packets sent to appchain, mapped by the id of validator sets
+    /// and the index of records.
+    vsc_packet_records: LookupMap<(u64, u32), VscPacketRecord>,
+    /// The count of VSC packet records, mapped by the id of validator sets.
+    vsc_packet_record_counts: LookupMap<u64, u32>,
+    /// The hash of the content and the timestamp of the last sent VSC packet.
+    last_sent_vsc_packet: Option<(Vec<u8>, u64)>,
+    /// The slash acks of double-sign slash packets, which will be sent in the next VSC packet.
+    pending_slash_acks: Vec<String>,
+    /// The pubkeys of validators waiting for slash by double-sign slash packets, which will be
+    /// removed in the next VSC packet.
+    pending_removing_pubkeys: Vec<Vec<u8>>,
+    /// The progress of migrating the old state, if the migration is not completed yet.
+    state_migration_progress: LazyOption<StateMigrationProgress>,
 }
 
 #[near_bindgen]
@@ -141,6 +206,29 @@ impl AppchainAnchor {
             appchain_state: AppchainState::Booting,
             pending_rewards: LookupArray::new(StorageKey::PendingRewards),
             pending_slash_packets: LookupArray::new(StorageKey::PendingSlashPackets),
+            slash_requests: UnorderedMap::new(StorageKey::SlashRequests),
+            failed_slash_requests: LookupArray::new(StorageKey::FailedSlashRequests),
+            dead_letter_slash_packets: LookupArray::new(StorageKey::DeadLetterSlashPackets),
+            received_slash_packets: LookupMap::new(StorageKey::ReceivedSlashPackets),
+            slash_histories: LookupArray::new(StorageKey::SlashHistories),
+            slash_history_indexes_of_validator: LookupMap::new(
+                StorageKey::SlashHistoryIndexesOfValidator,
+            ),
+            validator_operators: LookupMap::new(StorageKey::ValidatorOperators),
+            jail_ledger: JailLedger::new(),
+            validator_key_change_timestamps: LookupMap::new(
+                StorageKey::ValidatorKeyChangeTimestamps,
+            ),
+            applied_voting_powers: UnorderedMap::new(StorageKey::AppliedVotingPowers),
+            validator_set_fetch_in_flight: false,
+            last_fetch_status: None,
+            validator_set_builder: LazyOption::new(StorageKey::ValidatorSetBuilder, None),
+            vsc_packet_records: LookupMap::new(StorageKey::VscPacketRecords),
+            vsc_packet_record_counts: LookupMap::new(StorageKey::VscPacketRecordCounts),
+            last_sent_vsc_packet: None,
+            pending_slash_acks: Vec::new(),
+            pending_removing_pubkeys: Vec::new(),
+            state_migration_progress: LazyOption::new(StorageKey::StateMigrationProgress, None),
         }
     }
     //
@@ -185,6 +273,14 @@ impl AppchainAnchor {
             "This function can only be called by near-ibc contract."
         )
     }
+    // Assert that the old state is completely migrated, as the part not migrated yet
+    // can not be read.
+    fn assert_state_migrated(&self) {
+        assert!(
+            self.state_migration_progress.is_none(),
+            "State migration is in progress, call 'migrate_next_batch' first."
+        );
+    }
     /// Callback function for `ft_transfer_call` of NEP-141 compatible contracts
     pub fn ft_on_transfer(
         &mut self,
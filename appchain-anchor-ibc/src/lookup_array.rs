@@ -65,6 +65,9 @@ where
     ///
     pub fn get_slice_of(&self, start_index: &u64, quantity: Option<u64>) -> Vec<T> {
         let mut results = Vec::<T>::new();
+        if quantity == Some(0) {
+            return results;
+        }
         let start_index = match self.start_index > *start_index {
             true => self.start_index,
             false => *start_index,
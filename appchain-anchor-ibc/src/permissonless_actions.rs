@@ -1,10 +1,16 @@
 use crate::{
     anchor_viewer::AnchorViewer,
     contract_actions::{
+        near_ibc_callbacks::ext_near_ibc_callbacks,
         restaking_base_callbacks::ext_restaking_base_callbacks,
         reward_token_callbacks::ext_reward_token_callbacks,
     },
     ext_contracts::{ext_near_ibc, ext_restaking_base},
+    validator_set::ValidatorStatus,
+    voting_power::{
+        cap_voting_powers, sort_voting_powers, stakes_to_voting_powers,
+        VOTING_POWER_SHARE_DENOMINATOR,
+    },
     *,
 };
 use near_contract_standards::fungible_token::core::ext_ft_core;
@@ -14,20 +20,52 @@ use near_sdk::NearToken;
 pub trait PermissionlessActions {
     /// Fetch validator set from restaking base contract.
     fn fetch_validator_set_from_restaking_base(&mut self);
+    /// Continue building the validator set fetched from restaking base contract.
+    ///
+    /// The validator set becomes the latest validator set once it is completely built.
+    fn continue_building_validator_set(&mut self) -> ProcessingResult;
     /// Send VSC packet to appchain via near-ibc contract.
+    ///
+    /// A VSC packet with the same content as the last sent one can not be sent within
+    /// `min_interval_for_same_vsc_packet` in anchor settings.
     fn send_vsc_packet_to_appchain(&mut self);
     /// Distribute pending rewards to validators.
     fn distribute_pending_rewards(&mut self) -> ProcessingResult;
     /// Unjail the given validator.
+    ///
+    /// Can only be called by the validator itself or its operator, unless
+    /// permissionless unjail is enabled in anchor settings.
     fn unjail_validator(&mut self, validator_id: AccountId);
     /// Process the first pending slash packet.
     fn process_first_pending_slash_packet(&mut self);
+    /// Process up to `max_count` pending slash packets, and send a single VSC packet
+    /// for all of them.
+    ///
+    /// For a double-sign slash packet, a slash request is sent to restaking base contract,
+    /// and its slash ack is queued in `slash_request_callback` once the slash id is returned.
+    /// The queued slash acks are sent in the next VSC packet, which is the single VSC packet
+    /// of a following call of this function (even if there is no pending slash packet).
+    fn process_pending_slash_packets(&mut self, max_count: u32) -> ProcessingResult;
+    /// Resend the first failed slash request to restaking base contract.
+    ///
+    /// If the request still fails after `MAX_RETRY_COUNT_OF_SLASH_REQUEST` retries,
+    /// its slash packet is moved to the dead-letter queue.
+    fn retry_first_failed_slash_request(&mut self);
 }
 
 #[near_bindgen]
 impl PermissionlessActions for AppchainAnchor {
     //
     fn fetch_validator_set_from_restaking_base(&mut self) {
+        self.assert_state_migrated();
+        assert!(
+            !self.validator_set_fetch_in_flight,
+            "Fetching validator set from restaking base contract is in progress."
+        );
+        assert!(
+            self.validator_set_builder.is_none(),
+            "A validator set is being built."
+        );
         let anchor_settings = self.anchor_settings.get().unwrap();
         if let Some(latest_validator_set) = self.validator_set_histories.get_last() {
             assert!(
@@ -36,6 +74,7 @@ impl PermissionlessActions for AppchainAnchor {
                 "The interval between two validator sets is too short."
             );
         }
+        self.validator_set_fetch_in_flight = true;
         let consumer_chain_id = format!("cosmos:{}", self.appchain_id);
         ext_restaking_base::ext(self.restaking_base_contract.clone())
             .get_validator_set(consumer_chain_id, anchor_settings.max_count_of_validators)
@@ -45,14 +84,74 @@ impl PermissionlessActions for AppchainAnchor {
             );
     }
     //
+    fn continue_building_validator_set(&mut self) -> ProcessingResult {
+        let mut builder = self
+            .validator_set_builder
+            .get()
+            .expect("No validator set is being built.");
+        let max_gas = Gas::from_tgas(T_GAS_CAP_FOR_MULTI_TXS_PROCESSING);
+        if builder.rejection().is_none() {
+            let result = builder.build(
+                &self.validator_set_histories.get_last(),
+                &self.anchor_settings.get().unwrap(),
+                &self.jail_ledger,
+                max_gas,
+            );
+            if result.is_need_more_gas() {
+                log!(
+                    "Built {} of {} validators in validator set {}.",
+                    builder.added_count(),
+                    builder.total_count(),
+                    builder.validator_set().id()
+                );
+                self.validator_set_builder.set(&builder);
+                return result;
+            }
+            match self.get_voting_powers_of(builder.validator_set()) {
+                Ok(_) => {
+                    self.validator_set_builder.remove();
+                    self.complete_validator_set(builder);
+                    return ProcessingResult::Ok;
+                }
+                Err(err) => builder.reject(err),
+            }
+        }
+        //
+        // Clear the rejected validator set, as its storage will be reused by the next one.
+        //
+        let result = builder.clear(max_gas);
+        if result.is_ok() {
+            self.validator_set_builder.remove();
+            self.reject_validator_set(
+                U64::from(builder.validator_set().sequence()),
+                builder.rejection().unwrap(),
+            );
+        } else {
+            self.validator_set_builder.set(&builder);
+        }
+        result
+    }
+    //
     fn send_vsc_packet_to_appchain(&mut self) {
         if let Some(validator_set) = self.validator_set_histories.get_last() {
-            self.send_vsc_packet(
-                &validator_set,
-                &self.validator_set_histories.get_second_last(),
-                vec![],
-                vec![],
+            assert!(
+                !self.is_held_back(&validator_set),
+                "The latest validator set is held back, as it has too few validators."
             );
+            let vsc_packet_data = self.generate_vsc_packet_data(&validator_set, &vec![], &vec![]);
+            if let Some((last_hash, last_timestamp)) = &self.last_sent_vsc_packet {
+                let min_interval = self
+                    .anchor_settings
+                    .get()
+                    .unwrap()
+                    .min_interval_for_same_vsc_packet;
+                assert!(
+                    *last_hash != hash_of_vsc_packet_data(&vsc_packet_data)
+                        || env::block_timestamp() - last_timestamp >= min_interval.0,
+                    "The same VSC packet has been sent within the minimum interval."
+                );
+            }
+            self.send_vsc_packet_data(vsc_packet_data);
         }
     }
     //
@@ -79,21 +178,23 @@ impl PermissionlessActions for AppchainAnchor {
                 )
                     .as_str(),
                 );
-            let period = if !validator_set.jailed_validators().is_empty() && index > 0 {
+            let mut active_validators = validator_set.active_validators();
+            if index > 0 {
                 let previous_reward_distribution = self
                     .pending_rewards
                     .get(&(index - 1))
                     .expect("No previous reward distribution.");
-                Some((
-                    previous_reward_distribution.timestamp,
-                    reward_distribution.timestamp,
-                ))
-            } else {
-                None
-            };
+                active_validators.retain(|(validator_id, _)| {
+                    !self.jail_ledger.is_jailed_in_period(
+                        validator_id,
+                        previous_reward_distribution.timestamp,
+                        reward_distribution.timestamp,
+                    )
+                });
+            }
             let msg = FtTransferMessage::AnchorDepositRewardMsg(AnchorDepositRewardMsg {
                 consumer_chain_id: format!("cosmos:{}", self.appchain_id.clone()),
-                validator_set: validator_set.active_validators(period),
+                validator_set: active_validators,
                 sequence: validator_set.sequence().into(),
             });
             //
@@ -120,6 +221,7 @@ impl PermissionlessActions for AppchainAnchor {
     }
     //
     fn unjail_validator(&mut self, validator_id: AccountId) {
+        self.assert_state_migrated();
         let mut validator_set = self
             .validator_set_histories
             .get_last()
@@ -130,129 +232,447 @@ impl PermissionlessActions for AppchainAnchor {
             validator_id
         );
         let anchor_settings = self.anchor_settings.get().unwrap();
-        validator_set.unjail_validator(&validator_id, anchor_settings.min_unjail_interval.0);
-        self.validator_set_histories.update_last(&validator_set);
-        self.send_vsc_packet(
-            &validator_set,
-            &self.validator_set_histories.get_second_last(),
-            vec![],
-            vec![],
-        );
+        if !anchor_settings.permissionless_unjail_enabled {
+            let predecessor = env::predecessor_account_id();
+            assert!(
+                predecessor == validator_id
+                    || self
+                        .validator_operators
+                        .get(&validator_id)
+                        .is_some_and(|operator_id| operator_id == predecessor),
+                "Only the validator or its operator can unjail the validator."
+            );
+        }
+        validator_set.unjail_validator(&validator_id, &anchor_settings, &mut self.jail_ledger);
+        self.update_latest_validator_set(&validator_set, &[validator_id]);
+        self.send_vsc_packet(&validator_set, vec![], vec![]);
     }
     //
     fn process_first_pending_slash_packet(&mut self) {
-        if let Some(packet_string) = self.pending_slash_packets.get_first() {
-            self.internal_process_slash_packet(
-                &near_sdk::serde_json::from_str::<SlashPacketView>(packet_string.as_str())
-                    .expect("Invalid slash packet data."),
-            );
+        self.assert_state_migrated();
+        if let Some(slash_packet) = self.pending_slash_packets.get_first() {
+            let (removing_pubkeys, slash_acks) = self.process_slash_packet(&slash_packet);
             self.pending_slash_packets
                 .remove_first(env::prepaid_gas().saturating_sub(env::used_gas()));
-            log!("The first slash packet has been applied: {}", packet_string);
+            self.send_vsc_packet_for_slashing(removing_pubkeys, slash_acks);
         } else {
             log!("No pending slash packet.");
         }
     }
+    //
+    fn process_pending_slash_packets(&mut self, max_count: u32) -> ProcessingResult {
+        self.assert_state_migrated();
+        assert!(max_count > 0, "The max count should be greater than 0.");
+        let mut removing_pubkeys = Vec::new();
+        let mut slash_acks = Vec::new();
+        let mut processed_count = 0;
+        let mut result = ProcessingResult::Ok;
+        while let Some(slash_packet) = self.pending_slash_packets.get_first() {
+            if processed_count >= max_count
+                || env::used_gas() > Gas::from_tgas(T_GAS_CAP_FOR_MULTI_TXS_PROCESSING)
+            {
+                result = ProcessingResult::NeedMoreGas;
+                break;
+            }
+            let (mut packet_removing_pubkeys, mut packet_slash_acks) =
+                self.process_slash_packet(&slash_packet);
+            removing_pubkeys.append(&mut packet_removing_pubkeys);
+            slash_acks.append(&mut packet_slash_acks);
+            self.pending_slash_packets
+                .remove_first(env::prepaid_gas().saturating_sub(env::used_gas()));
+            processed_count += 1;
+        }
+        self.send_vsc_packet_for_slashing(removing_pubkeys, slash_acks);
+        result
+    }
+    //
+    fn retry_first_failed_slash_request(&mut self) {
+        let failed_slash_request = self
+            .failed_slash_requests
+            .get_first()
+            .expect("No failed slash request.");
+        self.failed_slash_requests
+            .remove_first(env::prepaid_gas().saturating_sub(env::used_gas()));
+        self.send_slash_request(
+            &failed_slash_request.slash_packet,
+            failed_slash_request.slash_items,
+            failed_slash_request.slash_history_index,
+            failed_slash_request.retry_count + 1,
+        );
+    }
 }
 
 impl AppchainAnchor {
+    /// Send a VSC packet for the given validator set.
     pub fn send_vsc_packet(
         &mut self,
         validator_set: &ValidatorSet,
-        previous_validator_set: &Option<ValidatorSet>,
         removing_pubkeys: Vec<Vec<u8>>,
         slash_acks: Vec<String>,
     ) {
+        let vsc_packet_data =
+            self.generate_vsc_packet_data(validator_set, &removing_pubkeys, &slash_acks);
+        self.send_vsc_packet_data(vsc_packet_data);
+    }
+    /// Resend the changes of the timed out VSC packet with the given record index.
+    ///
+    /// The packet data is not resent as it is, as its voting powers may be stale and
+    /// override the ones in later packets. Instead, a new VSC packet is generated from
+    /// the latest validator set, which moves the applied voting powers towards the latest
+    /// target voting powers, and carries the slash acks of the timed out packet and
+    /// the removals which are still valid.
+    pub fn resend_vsc_packet(&mut self, validator_set_id: &U64, record_index: u32) {
+        let record = self
+            .vsc_packet_records
+            .get(&(validator_set_id.0, record_index))
+            .expect("VSC packet record not found.");
+        assert_eq!(
+            record.state,
+            VscPacketState::TimedOut,
+            "Only timed out VSC packet can be resent."
+        );
+        let latest_vs = self
+            .validator_set_histories
+            .get_last()
+            .expect("No validator set exists, should not happen.");
+        let target_pubkeys: Vec<Vec<u8>> = self
+            .get_target_voting_powers_of(&latest_vs)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(public_key, _)| public_key)
+            .collect();
+        let removing_pubkeys = record
+            .packet_data
+            .validator_pubkeys
+            .into_iter()
+            .filter(|vkp| vkp.power.0 == 0 && !target_pubkeys.contains(&vkp.public_key))
+            .map(|vkp| vkp.public_key)
+            .collect();
+        self.send_vsc_packet(&latest_vs, removing_pubkeys, record.packet_data.slash_acks);
+    }
+    /// Send the given VSC packet data to appchain, and record the packet in the records
+    /// of its validator set.
+    ///
+    /// The voting powers in the packet are recorded as applied in appchain only when
+    /// the packet is acknowledged, or when near-ibc returns no sequence for it,
+    /// see `apply_voting_powers_in`.
+    fn send_vsc_packet_data(&mut self, vsc_packet_data: VscPacketData) {
         assert!(
             self.appchain_state == AppchainState::Active,
             "The state of appchain must be 'Active'."
         );
-        ext_near_ibc::ext(self.near_ibc_contract.clone()).send_vsc_packet(
-            self.get_chain_id(),
-            self.generate_vsc_packet_data(
-                validator_set,
-                previous_validator_set,
-                &removing_pubkeys,
-                &slash_acks,
-            ),
-            self.anchor_settings
-                .get()
-                .unwrap()
-                .vsc_packet_timeout_interval,
+        //
+        // The queued removing pubkeys and slash acks are included in the data
+        // by `generate_vsc_packet_data`.
+        //
+        self.pending_removing_pubkeys.clear();
+        self.pending_slash_acks.clear();
+        self.last_sent_vsc_packet = Some((
+            hash_of_vsc_packet_data(&vsc_packet_data),
+            env::block_timestamp(),
+        ));
+        let anchor_settings = self.anchor_settings.get().unwrap();
+        let validator_set_id = vsc_packet_data.validator_set_id;
+        let mut record_count = self.get_vsc_packet_record_count_of(&validator_set_id);
+        for chunk_data in split_vsc_packet_data(
+            vsc_packet_data,
+            anchor_settings.max_validator_updates_per_vsc_packet,
+        ) {
+            self.send_vsc_packet_chunk(&validator_set_id, record_count, chunk_data);
+            record_count += 1;
+        }
+        self.vsc_packet_record_counts
+            .insert(&validator_set_id.0, &record_count);
+    }
+    /// Send a (chunk of) VSC packet to appchain, and save it as the record with the given
+    /// index of its validator set.
+    fn send_vsc_packet_chunk(
+        &mut self,
+        validator_set_id: &U64,
+        record_index: u32,
+        chunk_data: VscPacketData,
+    ) {
+        self.vsc_packet_records.insert(
+            &(validator_set_id.0, record_index),
+            &VscPacketRecord {
+                sequence: None,
+                sent_timestamp: env::block_timestamp(),
+                state: VscPacketState::Sending,
+                updated_timestamp: env::block_timestamp(),
+                packet_data: chunk_data.clone(),
+            },
         );
+        ext_near_ibc::ext(self.near_ibc_contract.clone())
+            .send_vsc_packet(
+                self.get_chain_id(),
+                chunk_data,
+                self.anchor_settings
+                    .get()
+                    .unwrap()
+                    .vsc_packet_timeout_interval,
+            )
+            .then(
+                ext_near_ibc_callbacks::ext(env::current_account_id())
+                    .send_vsc_packet_callback(*validator_set_id, record_index),
+            );
     }
-    //
-    fn generate_vsc_packet_data(
+    /// Record the voting powers in the given (acknowledged) VSC packet as applied in appchain.
+    pub fn apply_voting_powers_in(&mut self, vsc_packet_data: &VscPacketData) {
+        for vkp in &vsc_packet_data.validator_pubkeys {
+            if vkp.power.0 > 0 {
+                self.applied_voting_powers
+                    .insert(&vkp.public_key, &vkp.power.0);
+            } else {
+                self.applied_voting_powers.remove(&vkp.public_key);
+            }
+        }
+    }
+    /// Get the count of VSC packets sent for the given validator set.
+    pub fn get_vsc_packet_record_count_of(&self, validator_set_id: &U64) -> u32 {
+        self.vsc_packet_record_counts
+            .get(&validator_set_id.0)
+            .unwrap_or(0)
+    }
+    /// Remove the records of VSC packets sent for the given validator set.
+    pub fn remove_vsc_packet_records_of(&mut self, validator_set_id: &U64) {
+        for index in 0..self.get_vsc_packet_record_count_of(validator_set_id) {
+            self.vsc_packet_records.remove(&(validator_set_id.0, index));
+        }
+        self.vsc_packet_record_counts.remove(&validator_set_id.0);
+    }
+    /// Get the count of packets of the last validator updates sent for the given validator set,
+    /// and the indexes of them which are not acknowledged yet.
+    ///
+    /// The packets sent without sequence are regarded as acknowledged, as their
+    /// acknowledgements can not be tracked.
+    pub fn get_outstanding_vsc_packet_chunks_of(
+        &self,
+        validator_set_id: &U64,
+    ) -> Option<(u32, Vec<u32>)> {
+        let record_count = self.get_vsc_packet_record_count_of(validator_set_id);
+        let chunk_count = self
+            .vsc_packet_records
+            .get(&(validator_set_id.0, record_count.checked_sub(1)?))?
+            .packet_data
+            .chunk
+            .map_or(1, |chunk| chunk.count);
+        let first_index = record_count.saturating_sub(chunk_count);
+        let outstanding_chunks = (first_index..record_count)
+            .filter(|index| {
+                self.vsc_packet_records
+                    .get(&(validator_set_id.0, *index))
+                    .map_or(true, |record| {
+                        record.state != VscPacketState::Acknowledged
+                            && record.state != VscPacketState::SentWithoutSequence
+                    })
+            })
+            .map(|index| index - first_index)
+            .collect();
+        Some((chunk_count, outstanding_chunks))
+    }
+    /// Whether all packets of the last validator updates sent for the given validator set
+    /// are acknowledged.
+    pub fn is_validator_set_delivered(&self, validator_set_id: &U64) -> bool {
+        self.get_outstanding_vsc_packet_chunks_of(validator_set_id)
+            .is_some_and(|(_, outstanding_chunks)| outstanding_chunks.is_empty())
+    }
+    /// Change the state of the VSC packet with the given IBC sequence in the records
+    /// of the given validator set, and return the index of the record and the changed record.
+    ///
+    /// Returns `None` if no record has the sequence, as the packet is unknown.
+    pub fn update_vsc_packet_state(
+        &mut self,
+        validator_set_id: &U64,
+        sequence: &U64,
+        state: VscPacketState,
+    ) -> Option<(u32, VscPacketRecord)> {
+        let record_count = self.get_vsc_packet_record_count_of(validator_set_id);
+        let (index, mut record) = (0..record_count)
+            .rev()
+            .filter_map(|index| {
+                self.vsc_packet_records
+                    .get(&(validator_set_id.0, index))
+                    .map(|record| (index, record))
+            })
+            .find(|(_, record)| record.sequence.as_ref() == Some(sequence))?;
+        record.state = state;
+        record.updated_timestamp = env::block_timestamp();
+        self.vsc_packet_records
+            .insert(&(validator_set_id.0, index), &record);
+        Some((index, record))
+    }
+    /// Get the voting powers of the active validators in the given validator set,
+    /// which will be sent to appchain in VSC packets.
+    ///
+    /// The stakes are converted by `voting_power_divisor` in anchor settings, the voting powers
+    /// are capped by `max_voting_power_share` in anchor settings, and the validators with
+    /// zero voting power are excluded.
+    pub fn get_voting_powers_of(
+        &self,
+        validator_set: &ValidatorSet,
+    ) -> Result<Vec<(AccountId, u64)>, String> {
+        let anchor_settings = self.anchor_settings.get().unwrap();
+        let (validator_ids, stakes): (Vec<AccountId>, Vec<Balance>) = validator_set
+            .active_validators()
+            .into_iter()
+            .map(|(validator_id, stake)| (validator_id, stake.0))
+            .unzip();
+        let powers = stakes_to_voting_powers(&stakes, anchor_settings.voting_power_divisor.0)?;
+        //
+        // Exclude the validators with zero voting power before capping, otherwise they are
+        // counted in the capping, and the others may not be able to share the total power.
+        //
+        let (validator_ids, powers): (Vec<AccountId>, Vec<u64>) = validator_ids
+            .into_iter()
+            .zip(powers)
+            .filter(|(_, power)| *power > 0)
+            .unzip();
+        Ok(validator_ids
+            .into_iter()
+            .zip(cap_voting_powers(
+                &powers,
+                anchor_settings.max_voting_power_share,
+            ))
+            .filter(|(_, power)| *power > 0)
+            .collect())
+    }
+    /// Whether the given validator set is held back from VSC packets, as it has less
+    /// validators with voting power than `min_count_of_validators` in anchor settings.
+    pub fn is_held_back(&self, validator_set: &ValidatorSet) -> bool {
+        let min_count = self.anchor_settings.get().unwrap().min_count_of_validators;
+        self.get_voting_powers_of(validator_set)
+            .map_or(true, |powers| (powers.len() as u32) < min_count)
+    }
+    /// Get the target voting powers of the given validator set, mapped by the public keys
+    /// of validators, in the canonical order of CometBFT.
+    pub fn get_target_voting_powers_of(
+        &self,
+        validator_set: &ValidatorSet,
+    ) -> Result<Vec<(Vec<u8>, u64)>, String> {
+        let mut target_powers: Vec<(Vec<u8>, u64)> = self
+            .get_voting_powers_of(validator_set)?
+            .into_iter()
+            .map(|(validator_id, power)| {
+                (
+                    self.validator_id_to_pubkey_map.get(&validator_id).unwrap(),
+                    power,
+                )
+            })
+            .collect();
+        sort_voting_powers(&mut target_powers);
+        Ok(target_powers)
+    }
+    /// Generate the data of VSC packet, which moves the voting powers applied in appchain
+    /// towards the target voting powers of the given validator set.
+    ///
+    /// If the total change of voting powers exceeds `max_voting_power_change_share` of
+    /// the total applied voting power, the changes are scaled down, and the rest of them
+    /// will be applied in the following VSC packets. The removing pubkeys are always applied
+    /// immediately.
+    ///
+    /// If the given validator set is held back, only the removing pubkeys are applied.
+    ///
+    /// The removing pubkeys and slash acks queued by double-sign slash packets are
+    /// also included, they are cleared once the packet is sent.
+    pub fn generate_vsc_packet_data(
         &self,
         validator_set: &ValidatorSet,
-        previous_validator_set: &Option<ValidatorSet>,
         removing_pubkeys: &Vec<Vec<u8>>,
         slash_acks: &Vec<String>,
     ) -> VscPacketData {
-        let vs_pubkeys = validator_set
-            .active_validators(None)
+        let mut removing_pubkeys = removing_pubkeys.clone();
+        for pubkey in &self.pending_removing_pubkeys {
+            if !removing_pubkeys.contains(pubkey) {
+                removing_pubkeys.push(pubkey.clone());
+            }
+        }
+        let mut slash_acks = slash_acks.clone();
+        for slash_ack in &self.pending_slash_acks {
+            if !slash_acks.contains(slash_ack) {
+                slash_acks.push(slash_ack.clone());
+            }
+        }
+        let applied_powers = self.applied_voting_powers.to_vec();
+        let target_powers = match self.is_held_back(validator_set) {
+            true => applied_powers.clone(),
+            false => self
+                .get_target_voting_powers_of(validator_set)
+                .unwrap_or_else(|err| panic!("{}", err)),
+        };
+        //
+        // Collect the changes (public key, applied power, target power) of all validators.
+        //
+        let mut changes = applied_powers
             .iter()
-            .map(|(validator_id, stake)| ValidatorKeyAndPower {
-                public_key: self.validator_id_to_pubkey_map.get(&validator_id).unwrap(),
-                power: U64::from((stake.0 / NEAR_SCALE) as u64),
-            })
-            .filter(|vkp| vkp.power.0 > 0)
-            .collect::<Vec<ValidatorKeyAndPower>>();
-        let mut validator_pubkeys = match previous_validator_set {
-            Some(previous_validator_set) => {
-                let mut previous_vs_pubkeys = previous_validator_set
-                    .active_validators(None)
-                    .iter()
-                    .map(|(validator_id, stake)| ValidatorKeyAndPower {
-                        public_key: self.validator_id_to_pubkey_map.get(&validator_id).unwrap(),
-                        power: U64::from((stake.0 / NEAR_SCALE) as u64),
-                    })
-                    .filter(|vkp| vkp.power.0 > 0)
-                    .collect::<Vec<ValidatorKeyAndPower>>();
-                for pvkp in &mut previous_vs_pubkeys {
-                    if let Some(vkp) = vs_pubkeys
+            .map(|(public_key, applied_power)| {
+                let target_power = match removing_pubkeys.contains(public_key) {
+                    true => 0,
+                    false => target_powers
                         .iter()
-                        .find(|vkp| vkp.public_key == pvkp.public_key)
-                    {
-                        pvkp.power = vkp.power;
-                    } else {
-                        pvkp.power = U64::from(0);
-                    }
-                }
-                vs_pubkeys.iter().for_each(|vkp| {
-                    if previous_vs_pubkeys
-                        .iter()
-                        .find(|pvkp| pvkp.public_key == vkp.public_key)
-                        .is_none()
-                    {
-                        previous_vs_pubkeys.push(vkp.clone());
-                    }
-                });
-                previous_vs_pubkeys.clone()
+                        .find(|(pk, _)| pk == public_key)
+                        .map_or(0, |(_, power)| *power),
+                };
+                (public_key.clone(), *applied_power, target_power)
+            })
+            .collect::<Vec<(Vec<u8>, u64, u64)>>();
+        for (public_key, target_power) in &target_powers {
+            if !removing_pubkeys.contains(public_key)
+                && !changes.iter().any(|(pk, _, _)| pk == public_key)
+            {
+                changes.push((public_key.clone(), 0, *target_power));
             }
-            None => vs_pubkeys.clone(),
-        };
-        for pvkp in &mut validator_pubkeys {
-            if removing_pubkeys.contains(&pvkp.public_key.clone()) {
-                pvkp.power = U64::from(0);
+        }
+        //
+        // Limit the total change of voting powers.
+        //
+        let max_change_share = self
+            .anchor_settings
+            .get()
+            .unwrap()
+            .max_voting_power_change_share as u128;
+        let applied_total_power: u128 = applied_powers.iter().map(|(_, p)| *p as u128).sum();
+        let total_change: u128 = changes
+            .iter()
+            .filter(|(pk, _, _)| !removing_pubkeys.contains(pk))
+            .map(|(_, applied, target)| applied.abs_diff(*target) as u128)
+            .sum();
+        let max_total_change =
+            applied_total_power * max_change_share / VOTING_POWER_SHARE_DENOMINATOR as u128;
+        if max_change_share > 0 && applied_total_power > 0 && total_change > max_total_change {
+            for (public_key, applied, target) in changes.iter_mut() {
+                if removing_pubkeys.contains(public_key) || *applied == *target {
+                    continue;
+                }
+                let step = (applied.abs_diff(*target) as u128 * max_total_change / total_change)
+                    .max(1) as u64;
+                *target = match *target > *applied {
+                    true => *applied + step,
+                    false => *applied - step,
+                };
             }
         }
-        for pubkey in removing_pubkeys {
-            if validator_pubkeys
-                .iter()
-                .find(|vkp| vkp.public_key == *pubkey)
-                .is_none()
-            {
-                validator_pubkeys.push(ValidatorKeyAndPower {
-                    public_key: pubkey.clone(),
-                    power: U64::from(0),
-                });
+        let mut powers = changes
+            .into_iter()
+            .map(|(public_key, _, power)| (public_key, power))
+            .collect::<Vec<(Vec<u8>, u64)>>();
+        for pubkey in &removing_pubkeys {
+            if !powers.iter().any(|(public_key, _)| public_key == pubkey) {
+                powers.push((pubkey.clone(), 0));
             }
         }
+        sort_voting_powers(&mut powers);
+        let validator_pubkeys = powers
+            .into_iter()
+            .map(|(public_key, power)| ValidatorKeyAndPower {
+                public_key,
+                power: U64::from(power),
+            })
+            .collect::<Vec<ValidatorKeyAndPower>>();
         VscPacketData {
             validator_pubkeys,
             validator_set_id: U64::from(validator_set.id()),
-            slash_acks: slash_acks.clone(),
+            slash_acks,
+            chunk: None,
         }
     }
     //
@@ -266,70 +686,774 @@ impl AppchainAnchor {
         }
         None
     }
+    /// Send a VSC packet based on the latest validator set, for the given
+    /// removing pubkeys and slash acks, and the queued ones of double-sign slash packets.
+    fn send_vsc_packet_for_slashing(
+        &mut self,
+        removing_pubkeys: Vec<Vec<u8>>,
+        slash_acks: Vec<String>,
+    ) {
+        if removing_pubkeys.is_empty()
+            && slash_acks.is_empty()
+            && self.pending_removing_pubkeys.is_empty()
+            && self.pending_slash_acks.is_empty()
+        {
+            return;
+        }
+        let validator_set = self
+            .validator_set_histories
+            .get_last()
+            .expect("No validator set exists, should not happen.");
+        self.send_vsc_packet(&validator_set, removing_pubkeys, slash_acks);
+    }
+    /// Process a pending slash packet.
     ///
-    pub fn internal_process_slash_packet(&mut self, slash_packet_view: &SlashPacketView) {
+    /// If the slash packet can not be applied, it will be moved to the dead-letter queue
+    /// and nothing will be returned.
+    fn process_slash_packet(
+        &mut self,
+        slash_packet: &SlashPacketRecord,
+    ) -> (Vec<Vec<u8>>, Vec<String>) {
+        match self.internal_process_slash_packet(slash_packet) {
+            Ok(result) => {
+                log!("The slash packet has been applied: {:?}", slash_packet);
+                result
+            }
+            Err(reason) => {
+                let validator_id = slash_packet
+                    .validator_address
+                    .as_ref()
+                    .and_then(|address| self.validator_address_to_id_map.get(address));
+                self.record_slash_history(
+                    slash_packet,
+                    validator_id,
+                    SlashAction::Rejected(reason.clone()),
+                );
+                let mut dead_letter = DeadLetterSlashPacket {
+                    index: U64::from(0),
+                    slash_packet: slash_packet.clone(),
+                    reason,
+                    timestamp: env::block_timestamp(),
+                };
+                self.dead_letter_slash_packets.append(&mut dead_letter);
+                emit_nep297_event("SLASH_PACKET_REJECTED", &dead_letter);
+                (vec![], vec![])
+            }
+        }
+    }
+    /// Apply the slash packet to the latest validator set.
+    ///
+    /// Returns the pubkeys to be removed and the slash acks to be included in
+    /// the next VSC packet, or the reason why the slash packet can not be applied.
+    pub fn internal_process_slash_packet(
+        &mut self,
+        slash_packet: &SlashPacketRecord,
+    ) -> Result<(Vec<Vec<u8>>, Vec<String>), String> {
         let mut validator_set = self
             .validator_set_histories
             .get_last()
             .expect("No validator set exists, should not happen.");
-        let slashing_validator = slash_packet_view.clone().validator.expect(
-            format!(
-                "Validator is empty, invalid slash packet: {}",
-                near_sdk::serde_json::to_string(slash_packet_view).unwrap()
-            )
-            .as_str(),
-        );
+        let validator_address = slash_packet
+            .validator_address
+            .clone()
+            .ok_or("Validator is empty in slash packet.".to_string())?;
         let validator_id = self
             .validator_address_to_id_map
-            .get(&slashing_validator.address)
-            .expect(
-                format!(
-                    "Validator address {:?} is not registered.",
-                    slashing_validator.address
-                )
-                .as_str(),
-            );
-        let removing_pubkeys = if validator_set.id() > slash_packet_view.valset_update_id + 1 {
-            emit_nep297_event("SLASH_PACKET_TOO_OLD", slash_packet_view);
-            vec![]
-        } else {
-            self.validator_id_to_pubkey_map
-                .get(&validator_id)
-                .map_or(vec![], |v| vec![v])
-        };
+            .get(&validator_address)
+            .ok_or(format!(
+                "Validator address {:?} is not registered.",
+                validator_address
+            ))?;
+        let validator_status = validator_set
+            .get_validator(&validator_id)
+            .ok_or(format!(
+                "Validator {} is not in the latest validator set {}.",
+                validator_id,
+                validator_set.id()
+            ))?
+            .status;
         let anchor_settings = self.anchor_settings.get().unwrap();
-        match slash_packet_view.infraction.as_str() {
+        match slash_packet.infraction.as_str() {
             "INFRACTION_DOWNTIME" => {
-                validator_set.jail_validator(&validator_id);
-                self.validator_set_histories.update_last(&validator_set);
-                self.send_vsc_packet(
+                if validator_status != ValidatorStatus::Active {
+                    return Err(format!(
+                        "Validator {} can not be jailed in status {:?}.",
+                        validator_id, validator_status
+                    ));
+                }
+                //
+                // The validator may still have an open jail record while it is active in
+                // the latest validator set, e.g. a slash request on it is vetoed, or it
+                // rejoined the validator set after leaving it while jailed.
+                //
+                if self.jail_ledger.get_open_record(&validator_id).is_some() {
+                    return Err(format!(
+                        "Validator {} is already jailed in jail ledger.",
+                        validator_id
+                    ));
+                }
+                let removing_pubkeys = self.get_removing_pubkeys_for_slashing(
                     &validator_set,
-                    &self.validator_set_histories.get_second_last(),
+                    &validator_id,
+                    slash_packet,
+                );
+                validator_set.jail_validator(&validator_id, &mut self.jail_ledger);
+                self.update_latest_validator_set(&validator_set, &[validator_id.clone()]);
+                self.record_slash_history(slash_packet, Some(validator_id), SlashAction::Jailed);
+                Ok((
                     removing_pubkeys,
                     vec![calculate_bech32_address(
                         anchor_settings.appchain_address_bech32_hrp.clone(),
-                        slashing_validator.address.clone(),
+                        validator_address,
                     )],
-                );
+                ))
             }
             "INFRACTION_DOUBLE_SIGN" => {
-                unimplemented!("INFRACTION_DOUBLE_SIGN");
-                // let validator = validator_set.get_validator(&validator_id).expect(
-                //     format!(
-                //         "Validator for address {:?} is not found in validator set {}.",
-                //         slashing_validator.address, slash_packet_data.valset_update_id
-                //     )
-                //     .as_str(),
-                // );
-                // let slash_items = vec![(validator.validator_id, U128::from(validator.total_stake))];
-                // ext_restaking_base::ext(self.restaking_base_contract.clone())
-                //     .slash_request(self.appchain_id.clone(), slash_items.clone(), String::new())
-                //     .then(
-                //         ext_restaking_base_callbacks::ext(env::current_account_id())
-                //             .slash_request_callback(slash_items),
-                //     );
+                if validator_status != ValidatorStatus::Active
+                    && validator_status != ValidatorStatus::Jailed
+                {
+                    return Err(format!(
+                        "Validator {} can not be slashed in status {:?}.",
+                        validator_id, validator_status
+                    ));
+                }
+                let offending_validator_set = self
+                    .validator_set_histories
+                    .get(&slash_packet.valset_update_id.0)
+                    .unwrap_or(validator_set);
+                let validator =
+                    offending_validator_set
+                        .get_validator(&validator_id)
+                        .ok_or(format!(
+                            "Validator {} is not found in validator set {}.",
+                            validator_id,
+                            offending_validator_set.id()
+                        ))?;
+                let slash_items = vec![(validator.validator_id, U128::from(validator.total_stake))];
+                let slash_history_index = self.record_slash_history(
+                    slash_packet,
+                    Some(validator_id),
+                    SlashAction::SlashRequested,
+                );
+                self.send_slash_request(slash_packet, slash_items, slash_history_index, 0);
+                Ok((vec![], vec![]))
+            }
+            infraction => Err(format!("Unknown infraction: {}", infraction)),
+        }
+    }
+    /// Send slash request to restaking base contract.
+    pub fn send_slash_request(
+        &self,
+        slash_packet: &SlashPacketRecord,
+        slash_items: Vec<(AccountId, U128)>,
+        slash_history_index: U64,
+        retry_count: u32,
+    ) {
+        ext_restaking_base::ext(self.restaking_base_contract.clone())
+            .slash_request(
+                format!("cosmos:{}", self.appchain_id),
+                slash_items.clone(),
+                slash_packet.evidence_sha256_hash(),
+            )
+            .then(
+                ext_restaking_base_callbacks::ext(env::current_account_id())
+                    .slash_request_callback(
+                        slash_packet.clone(),
+                        slash_items,
+                        slash_history_index,
+                        retry_count,
+                    ),
+            );
+    }
+    /// Record the result of processing a slash packet in slash histories.
+    ///
+    /// Returns the index of the new slash history record.
+    fn record_slash_history(
+        &mut self,
+        slash_packet: &SlashPacketRecord,
+        validator_id: Option<AccountId>,
+        action: SlashAction,
+    ) -> U64 {
+        let anchor_settings = self.anchor_settings.get().unwrap();
+        let record = self.slash_histories.append(&mut SlashHistoryRecord {
+            index: U64::from(0),
+            validator_id: validator_id.clone(),
+            address_in_appchain: slash_packet.validator_address.clone().map_or(
+                String::new(),
+                |address| {
+                    calculate_bech32_address(anchor_settings.appchain_address_bech32_hrp, address)
+                },
+            ),
+            infraction: slash_packet.infraction.clone(),
+            valset_update_id: slash_packet.valset_update_id,
+            received_timestamp: slash_packet.received_timestamp,
+            processed_timestamp: env::block_timestamp(),
+            action,
+            slash_id: None,
+        });
+        if let Some(validator_id) = validator_id {
+            let mut indexes = self
+                .slash_history_indexes_of_validator
+                .get(&validator_id)
+                .unwrap_or_default();
+            indexes.push(record.index.0);
+            self.slash_history_indexes_of_validator
+                .insert(&validator_id, &indexes);
+        }
+        record.index
+    }
+    /// Update the action and slash id of a slash history record.
+    pub fn update_slash_history(
+        &mut self,
+        slash_history_index: &U64,
+        action: SlashAction,
+        slash_id: Option<U64>,
+    ) {
+        if let Some(mut record) = self.slash_histories.get(&slash_history_index.0) {
+            record.action = action;
+            record.slash_id = slash_id;
+            self.slash_histories.update(&slash_history_index.0, &record);
+        }
+    }
+    /// Save the changes of the latest validator set, and apply the status of the given
+    /// validators to the validator set being built (if any), so that the changes are not
+    /// lost when the new validator set is completed.
+    pub fn update_latest_validator_set(
+        &mut self,
+        latest_vs: &ValidatorSet,
+        validator_ids: &[AccountId],
+    ) {
+        self.validator_set_histories.update_last(latest_vs);
+        if let Some(mut builder) = self.validator_set_builder.get() {
+            for validator_id in validator_ids {
+                if let Some(validator) = latest_vs.get_validator(validator_id) {
+                    builder.sync_validator_status(&validator);
+                }
+            }
+            self.validator_set_builder.set(&builder);
+        }
+    }
+    /// Change the state of a slash request, and apply the result to the status of
+    /// corresponding validators in the latest validator set.
+    pub fn update_slash_request_state(
+        &mut self,
+        slash_id: &U64,
+        state: SlashRequestState,
+    ) -> SlashRequest {
+        let mut slash_request = self
+            .slash_requests
+            .get(&slash_id.0)
+            .expect(format!("Slash request {} is not found.", slash_id.0).as_str());
+        slash_request.state = state.clone();
+        slash_request.updated_timestamp = env::block_timestamp();
+        self.slash_requests.insert(&slash_id.0, &slash_request);
+        if let Some(mut latest_vs) = self.validator_set_histories.get_last() {
+            for (validator_id, _) in &slash_request.slash_items {
+                match state {
+                    SlashRequestState::Approved | SlashRequestState::Executed => {
+                        latest_vs.confirm_slashing_validator(validator_id, slash_id)
+                    }
+                    SlashRequestState::Rejected => latest_vs.cancel_slashing_validator(
+                        validator_id,
+                        slash_id,
+                        &self.jail_ledger,
+                    ),
+                    SlashRequestState::Requested => (),
+                }
+            }
+            let validator_ids: Vec<AccountId> = slash_request
+                .slash_items
+                .iter()
+                .map(|(validator_id, _)| validator_id.clone())
+                .collect();
+            self.update_latest_validator_set(&latest_vs, &validator_ids);
+        }
+        emit_nep297_event("SLASH_REQUEST_STATE_CHANGED", &slash_request);
+        slash_request
+    }
+    /// Whether a received slash packet is still in effect, that is, it is still pending,
+    /// or the validator is still jailed or slashed by it in the latest validator set.
+    ///
+    /// A packet with the same dedup key is only treated as a duplicate of it in this case.
+    pub fn is_slash_packet_in_effect(&self, index: u64, slash_packet: &SlashPacketRecord) -> bool {
+        if self.pending_slash_packets.get(&index).is_some() {
+            return true;
+        }
+        slash_packet
+            .validator_address
+            .as_ref()
+            .and_then(|address| self.validator_address_to_id_map.get(address))
+            .and_then(|validator_id| {
+                self.validator_set_histories
+                    .get_last()
+                    .and_then(|latest_vs| latest_vs.get_validator(&validator_id))
+            })
+            .is_some_and(|validator| {
+                matches!(
+                    validator.status,
+                    ValidatorStatus::Jailed
+                        | ValidatorStatus::WaitForSlash(_)
+                        | ValidatorStatus::Slashed
+                )
+            })
+    }
+    /// Get the pubkey of the slashing validator which should be removed from appchain
+    /// by the next VSC packet.
+    pub fn get_removing_pubkeys_for_slashing(
+        &self,
+        latest_validator_set: &ValidatorSet,
+        validator_id: &AccountId,
+        slash_packet: &SlashPacketRecord,
+    ) -> Vec<Vec<u8>> {
+        if latest_validator_set.id() > slash_packet.valset_update_id.0 + 1 {
+            emit_nep297_event("SLASH_PACKET_TOO_OLD", slash_packet);
+            vec![]
+        } else {
+            self.validator_id_to_pubkey_map
+                .get(validator_id)
+                .map_or(vec![], |v| vec![v])
+        }
+    }
+}
+
+/// Get the hash of the content of the given VSC packet data.
+fn hash_of_vsc_packet_data(vsc_packet_data: &VscPacketData) -> Vec<u8> {
+    env::sha256(&near_sdk::borsh::to_vec(vsc_packet_data).unwrap())
+}
+
+/// Split the validator updates in the given VSC packet data into multiple packets,
+/// each of which has at most `max_updates` validator updates.
+///
+/// The packets keep the order of the validator updates, and the slash acks
+/// are carried by the last packet. Zero `max_updates` means no splitting.
+pub fn split_vsc_packet_data(
+    vsc_packet_data: VscPacketData,
+    max_updates: u32,
+) -> Vec<VscPacketData> {
+    let max_updates = max_updates as usize;
+    if max_updates == 0 || vsc_packet_data.validator_pubkeys.len() <= max_updates {
+        return vec![vsc_packet_data];
+    }
+    let chunks = vsc_packet_data
+        .validator_pubkeys
+        .chunks(max_updates)
+        .map(|chunk| chunk.to_vec())
+        .collect::<Vec<Vec<ValidatorKeyAndPower>>>();
+    let count = chunks.len() as u32;
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(index, validator_pubkeys)| {
+            let is_last = index as u32 + 1 == count;
+            VscPacketData {
+                validator_pubkeys,
+                validator_set_id: vsc_packet_data.validator_set_id,
+                slash_acks: match is_last {
+                    true => vsc_packet_data.slash_acks.clone(),
+                    false => Vec::new(),
+                },
+                chunk: Some(VscPacketChunk {
+                    index: index as u32,
+                    count,
+                    is_last,
+                }),
             }
-            _ => (),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ext_contracts::RestakingBaseValidatorSet;
+    use near_sdk::{test_utils::VMContextBuilder, testing_env};
+
+    fn setup_contract() -> AppchainAnchor {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id("appchain.registry.testnet".parse().unwrap())
+            .build());
+        let mut contract = AppchainAnchor::new(
+            "restaking-base.testnet".parse().unwrap(),
+            "lpos-market.testnet".parse().unwrap(),
+            "near-ibc.testnet".parse().unwrap(),
+            "reward-token.testnet".parse().unwrap(),
+        );
+        contract.anchor_settings.set(&AnchorSettings {
+            min_validator_staking_amount: U128::from(0),
+            max_voting_power_change_share: 1_000,
+            ..Default::default()
+        });
+        contract
+    }
+
+    fn pubkey_of(index: u8) -> Vec<u8> {
+        vec![index; 32]
+    }
+
+    /// Create a validator set with the given stakes (in NEAR) of validators.
+    fn setup_validator_set(contract: &mut AppchainAnchor, stakes: &[u128]) -> ValidatorSet {
+        let mut validator_set = ValidatorSet::new(&None, 0);
+        for (index, stake) in stakes.iter().enumerate() {
+            let validator_id: AccountId = format!("validator{}.testnet", index).parse().unwrap();
+            contract
+                .validator_id_to_pubkey_map
+                .insert(&validator_id, &pubkey_of(index as u8));
+            validator_set.add_validator(validator_id, *stake * NEAR_SCALE, ValidatorStatus::Active);
+        }
+        validator_set
+    }
+
+    /// Get the voting powers in the packet, mapped by the index of validators.
+    fn powers_in(vsc_packet_data: &VscPacketData) -> Vec<(u8, u64)> {
+        let mut powers: Vec<(u8, u64)> = vsc_packet_data
+            .validator_pubkeys
+            .iter()
+            .map(|vkp| (vkp.public_key[0], vkp.power.0))
+            .collect();
+        powers.sort();
+        powers
+    }
+
+    #[test]
+    fn test_get_voting_powers_of_with_stake_below_divisor() {
+        let mut contract = setup_contract();
+        let mut anchor_settings = contract.anchor_settings.get().unwrap();
+        anchor_settings.max_voting_power_share = 3_000;
+        contract.anchor_settings.set(&anchor_settings);
+        let mut validator_set = setup_validator_set(&mut contract, &[90, 10]);
+        for index in 2..4 {
+            let validator_id: AccountId = format!("validator{}.testnet", index).parse().unwrap();
+            contract
+                .validator_id_to_pubkey_map
+                .insert(&validator_id, &pubkey_of(index));
+            validator_set.add_validator(validator_id, NEAR_SCALE / 2, ValidatorStatus::Active);
         }
+        // The validators with stake below the divisor are not counted in the capping,
+        // so the cap can not be satisfied by the others, and they get the same power.
+        let voting_powers = contract.get_voting_powers_of(&validator_set).unwrap();
+        assert_eq!(
+            voting_powers
+                .iter()
+                .map(|(_, power)| *power)
+                .collect::<Vec<u64>>(),
+            vec![50, 50]
+        );
+    }
+
+    #[test]
+    fn test_reject_slash_request_restores_validator_status() {
+        let mut contract = setup_contract();
+        let mut validator_set = setup_validator_set(&mut contract, &[100, 100]);
+        let validator0: AccountId = "validator0.testnet".parse().unwrap();
+        let validator1: AccountId = "validator1.testnet".parse().unwrap();
+        validator_set.jail_validator(&validator0, &mut contract.jail_ledger);
+        let slash_id = U64::from(1);
+        validator_set.wait_for_slashing_validator(&validator0, slash_id);
+        validator_set.wait_for_slashing_validator(&validator1, slash_id);
+        contract.validator_set_histories.append(&mut validator_set);
+        contract.slash_requests.insert(
+            &slash_id.0,
+            &SlashRequest {
+                slash_id,
+                validator_set_id: U64::from(0),
+                slash_items: vec![
+                    (validator0.clone(), U128::from(1)),
+                    (validator1.clone(), U128::from(1)),
+                ],
+                evidence_sha256_hash: String::new(),
+                state: SlashRequestState::Requested,
+                requested_timestamp: 0,
+                updated_timestamp: 0,
+            },
+        );
+        contract.update_slash_request_state(&slash_id, SlashRequestState::Rejected);
+        // The jailed validator is still jailed after the slash request is cancelled.
+        let latest_vs = contract.validator_set_histories.get_last().unwrap();
+        assert_eq!(
+            latest_vs.get_validator(&validator0).unwrap().status,
+            ValidatorStatus::Jailed
+        );
+        assert_eq!(
+            latest_vs.get_validator(&validator1).unwrap().status,
+            ValidatorStatus::Active
+        );
+    }
+
+    #[test]
+    fn test_process_slash_packet_of_jailed_validator() {
+        let mut contract = setup_contract();
+        let mut validator_set = setup_validator_set(&mut contract, &[100, 100]);
+        let validator0: AccountId = "validator0.testnet".parse().unwrap();
+        // The validator is active in the latest validator set, but still has
+        // an open jail record.
+        contract.jail_ledger.jail(&validator0);
+        contract.validator_set_histories.append(&mut validator_set);
+        contract
+            .validator_address_to_id_map
+            .insert(&vec![0; 20], &validator0);
+        let slash_packet = SlashPacketRecord {
+            index: U64::from(0),
+            validator_address: Some(vec![0; 20]),
+            validator_power: 100,
+            valset_update_id: U64::from(0),
+            infraction: "INFRACTION_DOWNTIME".to_string(),
+            received_timestamp: 0,
+        };
+        // The slash packet is moved to the dead-letter queue instead of panicking.
+        assert_eq!(
+            contract.process_slash_packet(&slash_packet),
+            (vec![], vec![])
+        );
+        let dead_letter = contract.dead_letter_slash_packets.get_first().unwrap();
+        assert_eq!(
+            dead_letter.slash_packet.validator_address,
+            Some(vec![0; 20])
+        );
+        assert!(dead_letter.reason.contains("already jailed"));
+        assert_eq!(
+            contract
+                .validator_set_histories
+                .get_last()
+                .unwrap()
+                .get_validator(&validator0)
+                .unwrap()
+                .status,
+            ValidatorStatus::Active
+        );
+    }
+
+    #[test]
+    fn test_generate_vsc_packet_data_with_pending_slash_acks() {
+        let mut contract = setup_contract();
+        let validator_set = setup_validator_set(&mut contract, &[100, 100]);
+        contract.applied_voting_powers.insert(&pubkey_of(0), &100);
+        contract.applied_voting_powers.insert(&pubkey_of(1), &100);
+        contract.pending_removing_pubkeys = vec![pubkey_of(1)];
+        contract.pending_slash_acks = vec!["ack1".to_string()];
+        // The queued removing pubkeys and slash acks are included with the given ones.
+        let vsc_packet_data = contract.generate_vsc_packet_data(
+            &validator_set,
+            &vec![pubkey_of(1)],
+            &vec!["ack0".to_string(), "ack1".to_string()],
+        );
+        assert_eq!(powers_in(&vsc_packet_data), vec![(0, 100), (1, 0)]);
+        assert_eq!(
+            vsc_packet_data.slash_acks,
+            vec!["ack0".to_string(), "ack1".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_generate_vsc_packet_data_with_churn_limit() {
+        let mut contract = setup_contract();
+        let validator_set = setup_validator_set(&mut contract, &[200, 100, 100]);
+        // Nothing is applied, all of the target voting powers are applied at once.
+        let vsc_packet_data = contract.generate_vsc_packet_data(&validator_set, &vec![], &vec![]);
+        assert_eq!(
+            powers_in(&vsc_packet_data),
+            vec![(0, 200), (1, 100), (2, 100)]
+        );
+        assert_eq!(vsc_packet_data.validator_set_id, U64::from(0));
+        assert!(vsc_packet_data.chunk.is_none());
+        // The total change (200) exceeds 10% of the total applied voting power (200),
+        // so the changes are scaled down to 20 in total.
+        contract.applied_voting_powers.insert(&pubkey_of(0), &100);
+        contract.applied_voting_powers.insert(&pubkey_of(1), &100);
+        let vsc_packet_data = contract.generate_vsc_packet_data(&validator_set, &vec![], &vec![]);
+        assert_eq!(
+            powers_in(&vsc_packet_data),
+            vec![(0, 110), (1, 100), (2, 10)]
+        );
+        // The removing pubkeys are applied immediately, and are not counted in the limit.
+        let vsc_packet_data =
+            contract.generate_vsc_packet_data(&validator_set, &vec![pubkey_of(1)], &vec![]);
+        assert_eq!(powers_in(&vsc_packet_data), vec![(0, 110), (1, 0), (2, 10)]);
+        // No limit.
+        let mut anchor_settings = contract.anchor_settings.get().unwrap();
+        anchor_settings.max_voting_power_change_share = 0;
+        contract.anchor_settings.set(&anchor_settings);
+        let vsc_packet_data = contract.generate_vsc_packet_data(&validator_set, &vec![], &vec![]);
+        assert_eq!(
+            powers_in(&vsc_packet_data),
+            vec![(0, 200), (1, 100), (2, 100)]
+        );
+        // The validator set is held back, only the removing pubkeys are applied.
+        anchor_settings.min_count_of_validators = 4;
+        contract.anchor_settings.set(&anchor_settings);
+        let vsc_packet_data = contract.generate_vsc_packet_data(&validator_set, &vec![], &vec![]);
+        assert_eq!(powers_in(&vsc_packet_data), vec![(0, 100), (1, 100)]);
+        let vsc_packet_data =
+            contract.generate_vsc_packet_data(&validator_set, &vec![pubkey_of(1)], &vec![]);
+        assert_eq!(powers_in(&vsc_packet_data), vec![(0, 100), (1, 0)]);
+    }
+
+    #[test]
+    fn test_split_vsc_packet_data() {
+        let vsc_packet_data = VscPacketData {
+            validator_pubkeys: (0..5)
+                .map(|index| ValidatorKeyAndPower {
+                    public_key: pubkey_of(index),
+                    power: U64::from(100),
+                })
+                .collect(),
+            validator_set_id: U64::from(1),
+            slash_acks: vec!["ack".to_string()],
+            chunk: None,
+        };
+        // The packet is not split if it is within the limit.
+        let packets = split_vsc_packet_data(vsc_packet_data.clone(), 5);
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].validator_pubkeys.len(), 5);
+        assert_eq!(packets[0].slash_acks, vec!["ack".to_string()]);
+        assert!(packets[0].chunk.is_none());
+        // The validator updates are split in order, and the slash acks are only
+        // in the last packet.
+        let packets = split_vsc_packet_data(vsc_packet_data.clone(), 2);
+        assert_eq!(
+            packets
+                .iter()
+                .map(|packet| packet.validator_pubkeys.len())
+                .collect::<Vec<usize>>(),
+            vec![2, 2, 1]
+        );
+        assert_eq!(
+            packets
+                .iter()
+                .flat_map(|packet| packet.validator_pubkeys.iter())
+                .map(|vkp| vkp.public_key[0])
+                .collect::<Vec<u8>>(),
+            vec![0, 1, 2, 3, 4]
+        );
+        for (index, packet) in packets.iter().enumerate() {
+            let is_last = index == 2;
+            assert_eq!(packet.validator_set_id, U64::from(1));
+            assert_eq!(packet.slash_acks.is_empty(), !is_last);
+            assert_eq!(
+                packet.chunk,
+                Some(VscPacketChunk {
+                    index: index as u32,
+                    count: 3,
+                    is_last,
+                })
+            );
+        }
+        // The packet is not split if there is no limit.
+        let packets = split_vsc_packet_data(vsc_packet_data, 0);
+        assert_eq!(packets.len(), 1);
+        assert!(packets[0].chunk.is_none());
+    }
+
+    #[test]
+    fn test_build_validator_set_with_auto_unjail() {
+        let mut contract = setup_contract();
+        let mut anchor_settings = contract.anchor_settings.get().unwrap();
+        anchor_settings.auto_unjail_enabled = true;
+        contract.anchor_settings.set(&anchor_settings);
+        let mut validator_set = setup_validator_set(&mut contract, &[100, 100, 100]);
+        let validator0: AccountId = "validator0.testnet".parse().unwrap();
+        let validator1: AccountId = "validator1.testnet".parse().unwrap();
+        validator_set.jail_validator(&validator0, &mut contract.jail_ledger);
+        // The validator1 is jailed later, its jail term is not over yet.
+        let min_unjail_interval = anchor_settings.min_unjail_interval.0;
+        testing_env!(VMContextBuilder::new()
+            .current_account_id("appchain.registry.testnet".parse().unwrap())
+            .block_timestamp(min_unjail_interval)
+            .build());
+        validator_set.jail_validator(&validator1, &mut contract.jail_ledger);
+        let last_vs = Some(validator_set);
+        let restaking_base_vs = RestakingBaseValidatorSet {
+            validator_set: (0..3)
+                .map(|index| {
+                    (
+                        format!("validator{}.testnet", index).parse().unwrap(),
+                        U128::from(100 * NEAR_SCALE),
+                    )
+                })
+                .collect(),
+            sequence: U64::from(1),
+        };
+        let build = |anchor_settings: &AnchorSettings, jail_ledger: &JailLedger| {
+            let mut builder = ValidatorSetBuilder::new(&last_vs, &restaking_base_vs);
+            assert!(builder
+                .build(&last_vs, anchor_settings, jail_ledger, Gas::from_tgas(300))
+                .is_ok());
+            builder.into_parts()
+        };
+        let (validator_set, auto_unjailed_validators) =
+            build(&anchor_settings, &contract.jail_ledger);
+        assert_eq!(
+            validator_set.get_validator(&validator0).unwrap().status,
+            ValidatorStatus::Active
+        );
+        assert_eq!(
+            validator_set.get_validator(&validator1).unwrap().status,
+            ValidatorStatus::Jailed
+        );
+        assert_eq!(auto_unjailed_validators, vec![validator0.clone()]);
+        // No validator is unjailed automatically if it is disabled.
+        anchor_settings.auto_unjail_enabled = false;
+        let (validator_set, auto_unjailed_validators) =
+            build(&anchor_settings, &contract.jail_ledger);
+        assert_eq!(
+            validator_set.get_validator(&validator0).unwrap().status,
+            ValidatorStatus::Jailed
+        );
+        assert!(auto_unjailed_validators.is_empty());
+    }
+
+    #[test]
+    fn test_get_validator_set_diff() {
+        let mut contract = setup_contract();
+        let mut validator_set = setup_validator_set(&mut contract, &[100, 100, 100]);
+        contract.validator_set_histories.append(&mut validator_set);
+        let validator_of =
+            |index: u8| -> AccountId { format!("validator{}.testnet", index).parse().unwrap() };
+        testing_env!(VMContextBuilder::new()
+            .current_account_id("appchain.registry.testnet".parse().unwrap())
+            .block_timestamp(100)
+            .build());
+        let mut next_validator_set = ValidatorSet::new(&Some(validator_set), 1);
+        next_validator_set.add_validator(
+            validator_of(0),
+            200 * NEAR_SCALE,
+            ValidatorStatus::Active,
+        );
+        next_validator_set.add_validator(
+            validator_of(1),
+            100 * NEAR_SCALE,
+            ValidatorStatus::Jailed,
+        );
+        next_validator_set.add_validator(
+            validator_of(3),
+            100 * NEAR_SCALE,
+            ValidatorStatus::Active,
+        );
+        contract
+            .validator_id_to_pubkey_map
+            .insert(&validator_of(3), &pubkey_of(3));
+        contract
+            .validator_set_histories
+            .append(&mut next_validator_set);
+        // Only the key change between the timestamps of the validator sets is included.
+        contract
+            .validator_key_change_timestamps
+            .insert(&validator_of(1), &vec![50]);
+        contract
+            .validator_key_change_timestamps
+            .insert(&validator_of(0), &vec![150]);
+        let ids_of = |changes: &Vec<ValidatorChangeView>| -> Vec<AccountId> {
+            changes.iter().map(|c| c.validator_id.clone()).collect()
+        };
+        let diff = contract
+            .get_validator_set_diff(U64::from(0), U64::from(1))
+            .unwrap();
+        assert_eq!(ids_of(&diff.joined), vec![validator_of(3)]);
+        assert_eq!(ids_of(&diff.left), vec![validator_of(2)]);
+        assert_eq!(ids_of(&diff.stake_changed), vec![validator_of(0)]);
+        assert_eq!(ids_of(&diff.status_changed), vec![validator_of(1)]);
+        assert_eq!(ids_of(&diff.key_changed), vec![validator_of(1)]);
+        assert!(contract
+            .get_validator_set_diff(U64::from(0), U64::from(2))
+            .is_none());
     }
 }
@@ -1,36 +1,10 @@
-use crate::{validator_set::Validator, *};
+use crate::validator_set::Validator;
+use crate::*;
 use near_sdk::borsh::{BorshDeserialize, BorshSerialize};
 use near_sdk::collections::LazyOption;
-use near_sdk::{env, near_bindgen, AccountId, IntoStorageKey, Timestamp};
+use near_sdk::{env, near_bindgen, AccountId, Gas, IntoStorageKey, Timestamp};
 use octopus_lpos::packet::consumer::SlashPacketData;
-
-#[derive(BorshDeserialize, BorshSerialize, Debug)]
-#[borsh(crate = "near_sdk::borsh")]
-pub struct OldValidatorSet {
-    /// The id of the validator set.
-    id: u64,
-    /// The set of account id of validators.
-    validator_id_set: UnorderedSet<AccountId>,
-    /// The validators data, mapped by their account id in NEAR protocol.
-    validators: LookupMap<AccountId, Validator>,
-    /// Total stake of current set
-    total_stake: Balance,
-    /// The sequence of the validator set in restaking base contract.
-    sequence: u64,
-    /// The timestamp of when this validator set is created.
-    timestamp: Timestamp,
-    /// Whether the validator set is matured in the corresponding appchain.
-    matured_in_appchain: bool,
-}
-
-#[derive(BorshDeserialize, BorshSerialize, Clone, Serialize, Deserialize, Debug)]
-#[borsh(crate = "near_sdk::borsh")]
-#[serde(crate = "near_sdk::serde")]
-pub struct OldRewardDistribution {
-    pub validator_set_id: U64,
-    pub amount: U128,
-    pub timestamp: Timestamp,
-}
+use std::collections::BTreeMap;
 
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
 #[borsh(crate = "near_sdk::borsh")]
@@ -52,11 +26,58 @@ pub struct OldAnchorSettings {
     pub vsc_packet_timeout_interval: U64,
     /// The minimum staking amount of a quliafied validator.
     pub min_validator_staking_amount: U128,
+    /// The ninimum time interval for the jailed validators can be unjailed (in nanoseconds).
+    pub min_unjail_interval: U64,
+    /// The HRP of bech32 address in corresponding appchain.
+    pub appchain_address_bech32_hrp: String,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct OldValidatorSet {
+    /// The id of the validator set.
+    pub id: u64,
+    /// The set of account id of validators.
+    pub validator_id_set: UnorderedSet<AccountId>,
+    /// The validators data, mapped by their account id in NEAR protocol.
+    pub validators: LookupMap<AccountId, Validator>,
+    /// Total stake of current set
+    pub total_stake: Balance,
+    /// The sequence of the validator set in restaking base contract.
+    pub sequence: u64,
+    /// The timestamp of when this validator set is created.
+    pub timestamp: Timestamp,
+    /// Whether the validator set is matured in the corresponding appchain.
+    pub matured_in_appchain: bool,
+    /// The jailed validators with their account id, jailed time and unjailed time.
+    pub jailed_validators: Vec<(AccountId, Timestamp, Timestamp)>,
+}
+
+/// The progress of migrating the old state, which is too large to be migrated
+/// in a single transaction.
+#[derive(BorshDeserialize, BorshSerialize)]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct StateMigrationProgress {
+    /// The index of the next pending slash packet to be migrated.
+    next_slash_packet_index: u64,
+    /// The end index of pending slash packets to be migrated (inclusive).
+    end_slash_packet_index: u64,
+    /// The index of the next validator set to be migrated.
+    next_validator_set_index: u64,
+    /// The end index of validator sets to be migrated (exclusive), which is the index
+    /// of the latest validator set migrated in `migrate_state`.
+    end_validator_set_index: u64,
+    /// The validators whose jail records are collected from the migrated validator sets,
+    /// but not moved to jail ledger yet.
+    validators_with_jail_records: Vec<AccountId>,
 }
 
 pub trait StorageMigration {
     fn migrate_state() -> Self;
-    fn migrate_pending_slash_data(&mut self);
+    /// Migrate the next batch of the old state which is left by `migrate_state`.
+    ///
+    /// Can be called by anyone, until it returns `ProcessingResult::Ok`.
+    fn migrate_next_batch(&mut self) -> ProcessingResult;
 }
 
 #[derive(BorshDeserialize, BorshSerialize)]
@@ -104,40 +125,6 @@ impl StorageMigration for AppchainAnchor {
         //
         // Migrate actions by old contract data.
         //
-        let index_range = old_contract.validator_set_histories.index_range();
-        for index in index_range.start_index.0..index_range.end_index.0 + 1 {
-            let old_vs_data = env::storage_read(&get_storage_key_in_lookup_array(
-                &StorageKey::ValidatorSetHistories,
-                &index,
-            ));
-            if let Some(old_vs_data) = old_vs_data {
-                let old_vs: OldValidatorSet =
-                    near_sdk::borsh::BorshDeserialize::try_from_slice(&old_vs_data).unwrap();
-                let new_vs = ValidatorSet::from_old_version(old_vs);
-                let new_vs_data = near_sdk::borsh::to_vec(&new_vs).unwrap();
-                env::storage_write(
-                    &get_storage_key_in_lookup_array(&StorageKey::ValidatorSetHistories, &index),
-                    &new_vs_data,
-                );
-            }
-        }
-        let index_range = old_contract.pending_rewards.index_range();
-        for index in index_range.start_index.0..index_range.end_index.0 + 1 {
-            let old_rd_data = env::storage_read(&get_storage_key_in_lookup_array(
-                &StorageKey::PendingRewards,
-                &index,
-            ));
-            if let Some(old_rd_data) = old_rd_data {
-                let old_rd: OldRewardDistribution =
-                    near_sdk::borsh::BorshDeserialize::try_from_slice(&old_rd_data).unwrap();
-                let new_rd = RewardDistribution::from_old_version(old_rd);
-                let new_rd_data = near_sdk::borsh::to_vec(&new_rd).unwrap();
-                env::storage_write(
-                    &get_storage_key_in_lookup_array(&StorageKey::PendingRewards, &index),
-                    &new_rd_data,
-                );
-            }
-        }
         let old_anchor_settings_data =
             env::storage_read(&StorageKey::AnchorSettings.into_storage_key());
         if let Some(old_anchor_settings_data) = old_anchor_settings_data {
@@ -154,8 +141,20 @@ impl StorageMigration for AppchainAnchor {
                     .min_interval_for_new_validator_set,
                 vsc_packet_timeout_interval: old_anchor_settings.vsc_packet_timeout_interval,
                 min_validator_staking_amount: old_anchor_settings.min_validator_staking_amount,
-                min_unjail_interval: U64::from(600 * 1_000_000_000),
-                appchain_address_bech32_hrp: "ottovalcons".to_string(),
+                min_unjail_interval: old_anchor_settings.min_unjail_interval,
+                appchain_address_bech32_hrp: old_anchor_settings.appchain_address_bech32_hrp,
+                slash_request_veto_window: U64::from(86400 * 1_000_000_000),
+                auto_unjail_enabled: false,
+                permissionless_unjail_enabled: false,
+                jail_escalation_window: U64::from(0),
+                max_unjail_interval: U64::from(604800 * 1_000_000_000),
+                max_voting_power_share: 0,
+                max_voting_power_change_share: 0,
+                voting_power_divisor: U128::from(NEAR_SCALE),
+                min_count_of_validators: 1,
+                vsc_packet_timeout_policy: VscPacketTimeoutPolicy::CloseAppchain,
+                min_interval_for_same_vsc_packet: U64::from(600 * 1_000_000_000),
+                max_validator_updates_per_vsc_packet: 0,
             };
             let new_anchor_settings_data = near_sdk::borsh::to_vec(&new_anchor_settings).unwrap();
             env::storage_write(
@@ -164,9 +163,24 @@ impl StorageMigration for AppchainAnchor {
             );
         }
         //
+        // The latest validator set is migrated at once, as it is used by most of the actions.
+        // The pending slash packets and the other validator sets are migrated in batches
+        // by `migrate_next_batch`.
+        //
+        let slash_packets_index_range = old_contract.pending_slash_packets.index_range();
+        let validator_sets_index_range = old_contract.validator_set_histories.index_range();
+        let mut progress = StateMigrationProgress {
+            next_slash_packet_index: slash_packets_index_range.start_index.0,
+            end_slash_packet_index: slash_packets_index_range.end_index.0,
+            next_validator_set_index: validator_sets_index_range.start_index.0,
+            end_validator_set_index: validator_sets_index_range.end_index.0,
+            validators_with_jail_records: Vec::new(),
+        };
+        migrate_validator_set_at(validator_sets_index_range.end_index.0, &mut progress);
+        //
         // Create the new contract using the data from the old contract.
         //
-        let new_contract = AppchainAnchor {
+        let mut new_contract = AppchainAnchor {
             appchain_id: old_contract.appchain_id,
             appchain_registry: old_contract.appchain_registry,
             owner: old_contract.owner,
@@ -181,37 +195,217 @@ impl StorageMigration for AppchainAnchor {
             anchor_settings: old_contract.anchor_settings,
             appchain_state: old_contract.appchain_state,
             pending_rewards: old_contract.pending_rewards,
-            pending_slash_packets: old_contract.pending_slash_packets,
+            pending_slash_packets: LookupArray::migrate_from(
+                StorageKey::PendingSlashPackets,
+                slash_packets_index_range.start_index.0,
+                slash_packets_index_range.end_index.0,
+            ),
+            slash_requests: UnorderedMap::new(StorageKey::SlashRequests),
+            failed_slash_requests: LookupArray::new(StorageKey::FailedSlashRequests),
+            dead_letter_slash_packets: LookupArray::new(StorageKey::DeadLetterSlashPackets),
+            received_slash_packets: LookupMap::new(StorageKey::ReceivedSlashPackets),
+            slash_histories: LookupArray::new(StorageKey::SlashHistories),
+            slash_history_indexes_of_validator: LookupMap::new(
+                StorageKey::SlashHistoryIndexesOfValidator,
+            ),
+            validator_operators: LookupMap::new(StorageKey::ValidatorOperators),
+            jail_ledger: JailLedger::new(),
+            validator_key_change_timestamps: LookupMap::new(
+                StorageKey::ValidatorKeyChangeTimestamps,
+            ),
+            applied_voting_powers: UnorderedMap::new(StorageKey::AppliedVotingPowers),
+            validator_set_fetch_in_flight: false,
+            last_fetch_status: None,
+            validator_set_builder: LazyOption::new(StorageKey::ValidatorSetBuilder, None),
+            vsc_packet_records: LookupMap::new(StorageKey::VscPacketRecords),
+            vsc_packet_record_counts: LookupMap::new(StorageKey::VscPacketRecordCounts),
+            last_sent_vsc_packet: None,
+            pending_slash_acks: Vec::new(),
+            pending_removing_pubkeys: Vec::new(),
+            state_migration_progress: LazyOption::new(
+                StorageKey::StateMigrationProgress,
+                Some(&progress),
+            ),
         };
         //
         // Migrate actions by new contract data.
         //
-
+        // Assume that the voting powers of the latest validator set have been applied
+        // in appchain.
+        if let Some(latest_validator_set) = new_contract.validator_set_histories.get_last() {
+            for (public_key, power) in new_contract
+                .get_target_voting_powers_of(&latest_validator_set)
+                .unwrap_or_default()
+            {
+                new_contract
+                    .applied_voting_powers
+                    .insert(&public_key, &power);
+            }
+        }
         //
         new_contract
     }
     //
-    fn migrate_pending_slash_data(&mut self) {
-        let index_range = self.pending_slash_packets.index_range();
-        for index in index_range.start_index.0..index_range.end_index.0 + 1 {
-            let packet_string = self.pending_slash_packets.get(&index).unwrap();
-            let old_slash_data =
-                near_sdk::serde_json::from_str::<SlashPacketData>(packet_string.as_str())
-                    .expect("Invalid slash packet data.");
-            self.pending_slash_packets.update(
-                &index,
-                &near_sdk::serde_json::to_string(&SlashPacketView {
-                    validator: old_slash_data.validator,
-                    valset_update_id: old_slash_data.valset_update_id,
-                    infraction: old_slash_data.infraction,
-                    received_timestamp: env::block_timestamp(),
-                })
-                .unwrap(),
-            );
+    fn migrate_next_batch(&mut self) -> ProcessingResult {
+        let mut progress = self
+            .state_migration_progress
+            .get()
+            .expect("No state migration is in progress.");
+        let max_gas = Gas::from_tgas(T_GAS_CAP_FOR_MULTI_TXS_PROCESSING);
+        while progress.next_slash_packet_index <= progress.end_slash_packet_index {
+            if env::used_gas() > max_gas {
+                self.state_migration_progress.set(&progress);
+                return ProcessingResult::NeedMoreGas;
+            }
+            let index = progress.next_slash_packet_index;
+            if let Some(slash_packet) = migrate_slash_packet_at(index) {
+                self.received_slash_packets
+                    .insert(&slash_packet.dedup_key(), &(index, 0));
+            }
+            progress.next_slash_packet_index += 1;
         }
+        while progress.next_validator_set_index < progress.end_validator_set_index {
+            if env::used_gas() > max_gas {
+                self.state_migration_progress.set(&progress);
+                return ProcessingResult::NeedMoreGas;
+            }
+            let index = progress.next_validator_set_index;
+            migrate_validator_set_at(index, &mut progress);
+            progress.next_validator_set_index += 1;
+        }
+        //
+        // Move the collected jail records to jail ledger, once all of the validator sets
+        // are migrated.
+        //
+        let mut collected_jail_records: LookupMap<AccountId, BTreeMap<Timestamp, Timestamp>> =
+            LookupMap::new(StorageKey::CollectedJailRecords);
+        while let Some(validator_id) = progress.validators_with_jail_records.last().cloned() {
+            if env::used_gas() > max_gas {
+                self.state_migration_progress.set(&progress);
+                return ProcessingResult::NeedMoreGas;
+            }
+            if let Some(records) = collected_jail_records.remove(&validator_id) {
+                for record in to_jail_records(records) {
+                    self.jail_ledger.append_record(&validator_id, &record);
+                }
+            }
+            progress.validators_with_jail_records.pop();
+        }
+        self.state_migration_progress.remove();
+        log!("State migration is completed.");
+        ProcessingResult::Ok
     }
 }
 
+/// Migrate the pending slash packet at the given index from the old format.
+///
+/// Returns the migrated slash packet, or `None` if it does not exist.
+fn migrate_slash_packet_at(index: u64) -> Option<SlashPacketRecord> {
+    let storage_key = get_storage_key_in_lookup_array(&StorageKey::PendingSlashPackets, &index);
+    let packet_string: String =
+        near_sdk::borsh::BorshDeserialize::try_from_slice(&env::storage_read(&storage_key)?)
+            .unwrap();
+    let slash_packet_view = near_sdk::serde_json::from_str::<SlashPacketView>(
+        packet_string.as_str(),
+    )
+    .unwrap_or_else(|_| {
+        let old_slash_data =
+            near_sdk::serde_json::from_str::<SlashPacketData>(packet_string.as_str())
+                .expect("Invalid slash packet data.");
+        SlashPacketView {
+            validator: old_slash_data.validator,
+            valset_update_id: old_slash_data.valset_update_id,
+            infraction: old_slash_data.infraction,
+            received_timestamp: env::block_timestamp(),
+        }
+    });
+    let mut slash_packet = SlashPacketRecord::from_view(&slash_packet_view);
+    slash_packet.set_index(&index);
+    env::storage_write(
+        &storage_key,
+        &near_sdk::borsh::to_vec(&slash_packet).unwrap(),
+    );
+    Some(slash_packet)
+}
+
+/// Migrate the validator set at the given index from the old format, and collect
+/// the jail records in it, which will be moved to jail ledger in the last batch.
+fn migrate_validator_set_at(index: u64, progress: &mut StateMigrationProgress) {
+    let storage_key = get_storage_key_in_lookup_array(&StorageKey::ValidatorSetHistories, &index);
+    if let Some(old_validator_set_data) = env::storage_read(&storage_key) {
+        let old_validator_set: OldValidatorSet =
+            near_sdk::borsh::BorshDeserialize::try_from_slice(&old_validator_set_data).unwrap();
+        let mut collected_jail_records: LookupMap<AccountId, BTreeMap<Timestamp, Timestamp>> =
+            LookupMap::new(StorageKey::CollectedJailRecords);
+        for (validator_id, jailed_time, unjailed_time) in old_validator_set.jailed_validators {
+            let mut records = collected_jail_records
+                .get(&validator_id)
+                .unwrap_or_else(|| {
+                    progress
+                        .validators_with_jail_records
+                        .push(validator_id.clone());
+                    BTreeMap::new()
+                });
+            merge_jail_record(&mut records, jailed_time, unjailed_time);
+            collected_jail_records.insert(&validator_id, &records);
+        }
+        let validator_set = ValidatorSet {
+            id: old_validator_set.id,
+            validator_id_set: old_validator_set.validator_id_set,
+            validators: old_validator_set.validators,
+            total_stake: old_validator_set.total_stake,
+            sequence: old_validator_set.sequence,
+            timestamp: old_validator_set.timestamp,
+            matured_in_appchain: old_validator_set.matured_in_appchain,
+            matured_timestamp: None,
+        };
+        env::storage_write(
+            &storage_key,
+            &near_sdk::borsh::to_vec(&validator_set).unwrap(),
+        );
+    }
+}
+
+/// Merge a jail record of a validator in an old validator set into the collected records
+/// of the validator, which are mapped by jailed time.
+///
+/// The same jail may be recorded in multiple validator sets, the latest unjailed time is kept.
+fn merge_jail_record(
+    records: &mut BTreeMap<Timestamp, Timestamp>,
+    jailed_time: Timestamp,
+    unjailed_time: Timestamp,
+) {
+    let recorded_unjailed_time = records.entry(jailed_time).or_insert(0);
+    if unjailed_time > *recorded_unjailed_time {
+        *recorded_unjailed_time = unjailed_time;
+    }
+}
+
+/// Convert the collected records of a validator to the jail records in time order,
+/// which do not overlap with each other.
+fn to_jail_records(records: BTreeMap<Timestamp, Timestamp>) -> Vec<JailRecord> {
+    let records: Vec<(Timestamp, Timestamp)> = records.into_iter().collect();
+    records
+        .iter()
+        .enumerate()
+        .map(|(i, (jailed_time, unjailed_time))| {
+            // Close the record if it is followed by a later jail of the validator.
+            let unjailed_time = match records.get(i + 1) {
+                Some((next_jailed_time, _))
+                    if *unjailed_time == 0 || unjailed_time > next_jailed_time =>
+                {
+                    *next_jailed_time
+                }
+                _ => *unjailed_time,
+            };
+            JailRecord {
+                jailed_timestamp: *jailed_time,
+                unjailed_timestamp: unjailed_time,
+            }
+        })
+        .collect()
+}
+
 pub fn get_storage_key_in_lookup_array<T: BorshSerialize>(
     prefix: &StorageKey,
     index: &T,
@@ -221,28 +415,52 @@ pub fn get_storage_key_in_lookup_array<T: BorshSerialize>(
     result
 }
 
-impl ValidatorSet {
-    pub fn from_old_version(old_vs: OldValidatorSet) -> Self {
-        ValidatorSet {
-            id: old_vs.id,
-            validator_id_set: old_vs.validator_id_set,
-            validators: old_vs.validators,
-            total_stake: old_vs.total_stake,
-            sequence: old_vs.sequence,
-            timestamp: old_vs.timestamp,
-            matured_in_appchain: old_vs.matured_in_appchain,
-            jailed_validators: vec![],
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_timestamps(records: Vec<JailRecord>) -> Vec<(Timestamp, Timestamp)> {
+        records
+            .into_iter()
+            .map(|record| (record.jailed_timestamp, record.unjailed_timestamp))
+            .collect()
     }
-}
 
-impl RewardDistribution {
-    pub fn from_old_version(old_rd: OldRewardDistribution) -> Self {
-        RewardDistribution {
-            validator_set_id: old_rd.validator_set_id,
-            amount: old_rd.amount,
-            timestamp: old_rd.timestamp,
-            distributed: false,
-        }
+    #[test]
+    fn test_merge_jail_record() {
+        let mut records = BTreeMap::new();
+        // The same jail in multiple validator sets, unjailed in the later one.
+        merge_jail_record(&mut records, 100, 0);
+        merge_jail_record(&mut records, 100, 200);
+        merge_jail_record(&mut records, 100, 0);
+        // Another jail which is not unjailed yet.
+        merge_jail_record(&mut records, 300, 0);
+        assert_eq!(
+            records.into_iter().collect::<Vec<(Timestamp, Timestamp)>>(),
+            vec![(100, 200), (300, 0)]
+        );
+    }
+
+    #[test]
+    fn test_to_jail_records() {
+        // The records which do not overlap are kept.
+        assert_eq!(
+            to_timestamps(to_jail_records(BTreeMap::from([
+                (100, 200),
+                (300, 400),
+                (500, 0)
+            ]))),
+            vec![(100, 200), (300, 400), (500, 0)]
+        );
+        // The open or overlapping records are closed by the following jail.
+        assert_eq!(
+            to_timestamps(to_jail_records(BTreeMap::from([
+                (100, 0),
+                (300, 600),
+                (500, 0)
+            ]))),
+            vec![(100, 300), (300, 500), (500, 0)]
+        );
+        assert!(to_jail_records(BTreeMap::new()).is_empty());
     }
 }
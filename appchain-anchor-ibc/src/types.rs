@@ -1,5 +1,5 @@
 use crate::{validator_set::ValidatorStatus, *};
-use near_sdk::{IntoStorageKey, Timestamp};
+use near_sdk::{json_types::I64, IntoStorageKey, Timestamp};
 use octopus_lpos::packet::consumer::Validator;
 
 pub type AppchainId = String;
@@ -52,6 +52,58 @@ pub struct AnchorSettings {
     pub min_unjail_interval: U64,
     /// The HRP of bech32 address in corresponding appchain.
     pub appchain_address_bech32_hrp: String,
+    /// The time window for the owner to veto a slash request (in nanoseconds).
+    pub slash_request_veto_window: U64,
+    /// Whether the jailed validators will be unjailed automatically in new validator set
+    /// after `min_unjail_interval`.
+    pub auto_unjail_enabled: bool,
+    /// Whether any account can unjail a jailed validator.
+    /// If not, only the validator itself or its operator can do it.
+    pub permissionless_unjail_enabled: bool,
+    /// The time window for counting previous jails of a validator (in nanoseconds).
+    /// The jail term is doubled for each previous jail within this window.
+    /// Zero means the jail term will not be escalated.
+    pub jail_escalation_window: U64,
+    /// The maximum jail term of a validator (in nanoseconds).
+    pub max_unjail_interval: U64,
+    /// The maximum share of total voting power of a validator (in basis points).
+    /// Zero means no limit.
+    pub max_voting_power_share: u16,
+    /// The maximum total change of voting powers in a VSC packet, as a share of
+    /// the total voting power applied in appchain (in basis points). Zero means no limit.
+    pub max_voting_power_change_share: u16,
+    /// The amount of stake for one unit of voting power in appchain.
+    pub voting_power_divisor: U128,
+    /// The minimum count of validators with voting power in a validator set.
+    /// A validator set with less validators will be held back from VSC packets.
+    pub min_count_of_validators: u32,
+    /// The policy for handling the timeout of VSC packets.
+    pub vsc_packet_timeout_policy: VscPacketTimeoutPolicy,
+    /// The minimum interval for sending a VSC packet with the same content
+    /// by `send_vsc_packet_to_appchain` (in nanoseconds).
+    pub min_interval_for_same_vsc_packet: U64,
+    /// The maximum count of validator updates in a VSC packet.
+    /// The validator updates exceeding it are split into multiple packets.
+    ///
+    /// Zero means no splitting. It should only be set to a positive value when near-ibc
+    /// and the appchain support the chunk marker of VSC packets, see `VscPacketData::chunk`.
+    pub max_validator_updates_per_vsc_packet: u32,
+}
+
+/// The policy for handling the timeout of VSC packets.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub enum VscPacketTimeoutPolicy {
+    /// Send a new VSC packet generated from the latest validator set, which carries
+    /// the slash acks and the removals of the timed out packet.
+    ///
+    /// Only applicable to an unordered channel, as an ordered channel is closed
+    /// on the timeout of a packet.
+    Resend,
+    /// Move the appchain to `Closing` state, as an ICS provider chain does
+    /// for the timeout of VSC packets.
+    CloseAppchain,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -167,14 +219,21 @@ pub struct ValidatorView {
     pub validator_id: AccountId,
     /// Total stake of the validator, including delegations of all delegators.
     pub total_stake: U128,
-    /// The voting power of the validator.
+    /// The voting power of the validator, calculated by its total stake.
     pub voting_power: U64,
+    /// The voting power of the validator which is sent to appchain, after applying
+    /// the status of the validator and the voting power cap.
+    pub effective_voting_power: U64,
     /// Whether the validator is slashed.
     pub status: ValidatorStatus,
     /// The public key the validator registered in anchor contract.
     pub registered_pubkey: String,
     /// The address of the validator in appchain.
     pub address_in_appchain: String,
+    /// The required jail term of the validator (in nanoseconds), if it is jailed.
+    pub jail_term: Option<U64>,
+    /// The earliest time that the validator can be unjailed, if it is jailed.
+    pub unjailable_timestamp: Option<U64>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -192,10 +251,132 @@ pub struct ValidatorSetView {
     pub timestamp: Timestamp,
     /// Whether the validator set is matured on appchain.
     pub matured_on_appchain: bool,
-    /// The jailed validators with their account id, jailed time and unjailed time.
+    /// The timestamp of when the validator set is matured on appchain, if it is known.
+    pub matured_timestamp: Option<Timestamp>,
+    /// The jailed validators with their account id, jailed time and unjailed time,
+    /// in the lifetime of the validator set.
     pub jailed_validators: Vec<(AccountId, Timestamp, Timestamp)>,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ValidatorChangeView {
+    /// The validator's id in NEAR protocol.
+    pub validator_id: AccountId,
+    /// Total stake of the validator in the earlier validator set.
+    pub old_total_stake: U128,
+    /// Total stake of the validator in the later validator set.
+    pub new_total_stake: U128,
+    /// The status of the validator in the earlier validator set.
+    pub old_status: Option<ValidatorStatus>,
+    /// The status of the validator in the later validator set.
+    pub new_status: Option<ValidatorStatus>,
+    /// The voting power of the validator in the earlier validator set.
+    pub old_voting_power: U64,
+    /// The voting power of the validator in the later validator set.
+    pub new_voting_power: U64,
+    /// The change of voting power of the validator.
+    pub voting_power_delta: I64,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ValidatorSetDiffView {
+    /// The id of the earlier validator set.
+    pub from_id: U64,
+    /// The id of the later validator set.
+    pub to_id: U64,
+    /// The validators which are only in the later validator set.
+    pub joined: Vec<ValidatorChangeView>,
+    /// The validators which are only in the earlier validator set.
+    pub left: Vec<ValidatorChangeView>,
+    /// The validators whose total stake is changed.
+    pub stake_changed: Vec<ValidatorChangeView>,
+    /// The validators whose status is changed.
+    pub status_changed: Vec<ValidatorChangeView>,
+    /// The validators whose public key is changed between the two validator sets.
+    pub key_changed: Vec<ValidatorChangeView>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ValidatorPowerView {
+    /// The validator's id in NEAR protocol, if the public key is still registered.
+    pub validator_id: Option<AccountId>,
+    /// The public key of the validator in appchain.
+    pub public_key: String,
+    /// The voting power of the validator.
+    pub voting_power: U64,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VotingPowersView {
+    /// The id of the latest validator set.
+    pub validator_set_id: U64,
+    /// The voting powers of the latest validator set, which will be applied in appchain.
+    pub target: Vec<ValidatorPowerView>,
+    /// The voting powers which have been acknowledged by appchain.
+    pub applied: Vec<ValidatorPowerView>,
+    /// Whether the target voting powers have been fully applied.
+    pub fully_applied: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ValidatorKeyAndPowerView {
+    /// The validator's id in NEAR protocol, if the public key is still registered.
+    pub validator_id: Option<AccountId>,
+    /// The public key of the validator in hex.
+    pub public_key_hex: String,
+    /// The public key of the validator in base64, with prefix `ed25519:`.
+    pub public_key_base64: String,
+    /// The address (in bech32) of the validator in appchain.
+    pub address_in_appchain: String,
+    /// The voting power of the validator in the packet.
+    pub power: U64,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VscPacketPreview {
+    /// The exact data of the VSC packets, in the order of sending.
+    pub packets: Vec<VscPacketData>,
+    /// The validator updates in all of the packets, with readable keys and addresses.
+    pub validator_pubkeys: Vec<ValidatorKeyAndPowerView>,
+    /// Whether the latest validator set is held back from VSC packets.
+    pub held_back: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct UnmaturedValidatorSetView {
+    /// The id of the validator set.
+    pub validator_set_id: U64,
+    /// The sequence of the validator set in restaking base contract.
+    pub sequence: U64,
+    /// The timestamp of when the validator set is created.
+    pub timestamp: Timestamp,
+    /// The time elapsed since the validator set is created (in nanoseconds).
+    pub age: U64,
+    /// Whether all packets of the last validator updates sent for the validator set
+    /// are acknowledged.
+    pub delivered: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VscPacketDeliveryView {
+    /// The id of the validator set.
+    pub validator_set_id: U64,
+    /// The count of packets of the last validator updates sent for the validator set.
+    pub chunk_count: u32,
+    /// The indexes of the packets of the last validator updates which are not acknowledged yet.
+    pub outstanding_chunks: Vec<u32>,
+    /// Whether all packets of the last validator updates are acknowledged.
+    pub delivered: bool,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(crate = "near_sdk::serde")]
 pub enum FtTransferMessage {
@@ -232,19 +413,43 @@ impl IndexedAndClearable for RewardDistribution {
     }
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(BorshDeserialize, BorshSerialize, Clone, Serialize, Deserialize)]
+#[borsh(crate = "near_sdk::borsh")]
 #[serde(crate = "near_sdk::serde")]
 pub struct ValidatorKeyAndPower {
     pub public_key: Vec<u8>,
     pub power: U64,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(BorshDeserialize, BorshSerialize, Clone, Serialize, Deserialize)]
+#[borsh(crate = "near_sdk::borsh")]
 #[serde(crate = "near_sdk::serde")]
 pub struct VscPacketData {
     pub validator_pubkeys: Vec<ValidatorKeyAndPower>,
     pub validator_set_id: U64,
     pub slash_acks: Vec<String>,
+    /// The position of this packet, if the validator updates are split into multiple packets.
+    ///
+    /// It is passed to near-ibc as a part of the packet data, which must carry it to
+    /// the appchain. The appchain must apply the chunks of a validator set in the order
+    /// of their index, and only once the last one is received. Otherwise the appchain
+    /// applies a partial validator set, so the splitting is disabled by default, see
+    /// `AnchorSettings::max_validator_updates_per_vsc_packet`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk: Option<VscPacketChunk>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub struct VscPacketChunk {
+    /// The index of this packet in the packets of the same validator updates, starting from 0.
+    pub index: u32,
+    /// The count of packets of the same validator updates.
+    pub count: u32,
+    /// Whether this is the last packet of the same validator updates,
+    /// which also carries the slash acks.
+    pub is_last: bool,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -257,3 +462,300 @@ pub struct SlashPacketView {
     pub infraction: String,
     pub received_timestamp: Timestamp,
 }
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub struct SlashPacketRecord {
+    /// The index of the record in the lookup array.
+    pub index: U64,
+    /// The address of the slashing validator in appchain.
+    pub validator_address: Option<Vec<u8>>,
+    /// The voting power of the slashing validator in appchain.
+    pub validator_power: i64,
+    /// The id of the validator set which the infraction happened in.
+    pub valset_update_id: U64,
+    /// Whether the slashing is for a downtime or a double-signing infraction.
+    pub infraction: String,
+    /// The timestamp of when the slash packet is received.
+    pub received_timestamp: Timestamp,
+}
+
+impl SlashPacketRecord {
+    ///
+    pub fn from_view(slash_packet_view: &SlashPacketView) -> Self {
+        Self {
+            index: U64::from(0),
+            validator_address: slash_packet_view
+                .validator
+                .as_ref()
+                .map(|validator| validator.address.clone()),
+            validator_power: slash_packet_view
+                .validator
+                .as_ref()
+                .map_or(0, |validator| validator.power),
+            valset_update_id: U64::from(slash_packet_view.valset_update_id),
+            infraction: slash_packet_view.infraction.clone(),
+            received_timestamp: slash_packet_view.received_timestamp,
+        }
+    }
+    /// The key for identifying duplicated slash packets, which is the sha256 hash of
+    /// validator address, valset update id and infraction.
+    ///
+    /// A packet is only treated as a duplicate while the first packet with the same key
+    /// is still in effect, see `AppchainAnchor::is_slash_packet_in_effect`.
+    pub fn dedup_key(&self) -> Vec<u8> {
+        env::sha256(
+            &near_sdk::borsh::to_vec(&(
+                self.validator_address.clone(),
+                self.valset_update_id.0,
+                self.infraction.clone(),
+            ))
+            .unwrap(),
+        )
+    }
+    /// The sha256 hash (in hex) of the original slash packet data,
+    /// which is used as the evidence of a slash request.
+    pub fn evidence_sha256_hash(&self) -> String {
+        let packet_data = json!({
+            "validator_address": self.validator_address.as_ref().map(hex::encode),
+            "validator_power": self.validator_power,
+            "valset_update_id": self.valset_update_id,
+            "infraction": self.infraction,
+        });
+        hex::encode(env::sha256(packet_data.to_string().as_bytes()))
+    }
+}
+
+impl IndexedAndClearable for SlashPacketRecord {
+    //
+    fn set_index(&mut self, index: &u64) {
+        self.index = U64::from(*index);
+    }
+    //
+    fn clear_extra_storage(&mut self, _max_gas: Gas) -> ProcessingResult {
+        ProcessingResult::Ok
+    }
+}
+
+/// The state of a slash request sent to restaking base contract.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub enum SlashRequestState {
+    /// The slash request is accepted by restaking base contract,
+    /// and is waiting for approval or veto.
+    Requested,
+    /// The slash request is approved by the owner of this contract.
+    Approved,
+    /// The slash request is vetoed by the owner of this contract,
+    /// or cancelled by restaking base contract.
+    Rejected,
+    /// The slashing is executed by restaking base contract.
+    Executed,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub struct SlashRequest {
+    /// The slash id returned by restaking base contract.
+    pub slash_id: U64,
+    /// The id of the validator set which the slash packet refers to.
+    pub validator_set_id: U64,
+    /// The validators and the amount of stake to be slashed.
+    pub slash_items: Vec<(AccountId, U128)>,
+    /// The sha256 hash (in hex) of the slash packet data.
+    pub evidence_sha256_hash: String,
+    /// The current state of the slash request.
+    pub state: SlashRequestState,
+    /// The timestamp of when the slash request is accepted by restaking base contract.
+    pub requested_timestamp: Timestamp,
+    /// The timestamp of the last state change.
+    pub updated_timestamp: Timestamp,
+}
+
+impl SlashRequest {
+    ///
+    pub fn is_open(&self) -> bool {
+        match self.state {
+            SlashRequestState::Requested | SlashRequestState::Approved => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub struct FailedSlashRequest {
+    /// The slash packet which the slash request is generated from.
+    pub slash_packet: SlashPacketRecord,
+    /// The validators and the amount of stake to be slashed.
+    pub slash_items: Vec<(AccountId, U128)>,
+    /// The index of the corresponding record in slash histories.
+    pub slash_history_index: U64,
+    /// The timestamp of when the slash request failed.
+    pub failed_timestamp: Timestamp,
+    /// The number of times the slash request has been retried.
+    pub retry_count: u32,
+}
+
+impl IndexedAndClearable for FailedSlashRequest {
+    //
+    fn set_index(&mut self, _index: &u64) {
+        ()
+    }
+    //
+    fn clear_extra_storage(&mut self, _max_gas: Gas) -> ProcessingResult {
+        ProcessingResult::Ok
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub struct DeadLetterSlashPacket {
+    /// The index of the record in the dead-letter queue.
+    pub index: U64,
+    /// The slash packet which can not be applied.
+    pub slash_packet: SlashPacketRecord,
+    /// The reason why the slash packet can not be applied.
+    pub reason: String,
+    /// The timestamp of when the slash packet is moved to the dead-letter queue.
+    pub timestamp: Timestamp,
+}
+
+impl IndexedAndClearable for DeadLetterSlashPacket {
+    //
+    fn set_index(&mut self, index: &u64) {
+        self.index = U64::from(*index);
+    }
+    //
+    fn clear_extra_storage(&mut self, _max_gas: Gas) -> ProcessingResult {
+        ProcessingResult::Ok
+    }
+}
+
+/// The result of processing a slash packet.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub enum SlashAction {
+    /// The validator is jailed.
+    Jailed,
+    /// A slash request is sent to restaking base contract.
+    SlashRequested,
+    /// The slash request failed to be sent to restaking base contract.
+    SlashRequestFailed,
+    /// The slash packet is moved to the dead-letter queue with the reason.
+    Rejected(String),
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub struct SlashHistoryRecord {
+    /// The index of the record in slash histories.
+    pub index: U64,
+    /// The id of the slashed validator, if the validator address is registered.
+    pub validator_id: Option<AccountId>,
+    /// The address (in bech32) of the slashed validator in appchain.
+    pub address_in_appchain: String,
+    /// Whether the slashing is for a downtime or a double-signing infraction.
+    pub infraction: String,
+    /// The id of the validator set which the infraction happened in.
+    pub valset_update_id: U64,
+    /// The timestamp of when the slash packet is received.
+    pub received_timestamp: Timestamp,
+    /// The timestamp of when the slash packet is processed.
+    pub processed_timestamp: Timestamp,
+    /// The result of processing the slash packet.
+    pub action: SlashAction,
+    /// The slash id returned by restaking base contract.
+    pub slash_id: Option<U64>,
+}
+
+impl IndexedAndClearable for SlashHistoryRecord {
+    //
+    fn set_index(&mut self, index: &u64) {
+        self.index = U64::from(*index);
+    }
+    //
+    fn clear_extra_storage(&mut self, _max_gas: Gas) -> ProcessingResult {
+        ProcessingResult::Ok
+    }
+}
+
+/// The result of fetching validator set from restaking base contract.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub enum ValidatorSetFetchResult {
+    /// A new validator set is created. It is held back from VSC packets if it has less
+    /// validators than `min_count_of_validators` in anchor settings.
+    Created {
+        validator_set_id: U64,
+        held_back: bool,
+    },
+    /// The new validator set is being built by `continue_building_validator_set`.
+    Building { validator_set_id: U64 },
+    /// The validator set is not changed, no new validator set is created.
+    Unchanged { validator_set_id: U64 },
+    /// The validator set is rejected with the reason.
+    Rejected(String),
+    /// The fetching failed with the reason.
+    Failed(String),
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub struct ValidatorSetFetchStatus {
+    /// The sequence of the fetched validator set, if it is got successfully.
+    pub sequence: Option<U64>,
+    /// The timestamp of when the fetching is finished.
+    pub timestamp: Timestamp,
+    /// The result of the fetching.
+    pub result: ValidatorSetFetchResult,
+}
+
+/// The state of a VSC packet sent to appchain.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub enum VscPacketState {
+    /// The packet is being sent by near-ibc contract.
+    Sending,
+    /// The packet is sent by near-ibc contract, waiting for acknowledgement.
+    Sent,
+    /// The packet failed to be sent by near-ibc contract.
+    SendFailed,
+    /// The packet is acknowledged by appchain.
+    Acknowledged,
+    /// The packet is acknowledged by appchain with the error.
+    AckError(String),
+    /// The packet is timed out.
+    TimedOut,
+    /// The packet is sent by near-ibc contract, but no IBC sequence is returned,
+    /// so its acknowledgement can not be tracked.
+    ///
+    /// The voting powers in it are regarded as applied in appchain once it is sent.
+    SentWithoutSequence,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub struct VscPacketRecord {
+    /// The IBC sequence of the packet, if it is returned by near-ibc contract.
+    pub sequence: Option<U64>,
+    /// The timestamp of when the packet is sent.
+    pub sent_timestamp: Timestamp,
+    /// The state of the packet.
+    pub state: VscPacketState,
+    /// The timestamp of the last change of the state.
+    pub updated_timestamp: Timestamp,
+    /// The data of the packet.
+    pub packet_data: VscPacketData,
+}
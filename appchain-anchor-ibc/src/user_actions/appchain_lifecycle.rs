@@ -55,21 +55,18 @@ impl AppchainLifecycleManager for AppchainAnchor {
             "Max clock drift must be greater than 0 and less than trusting period."
         );
         let init_vs = self.validator_set_histories.get(&0).unwrap();
-        let validators_bytes: Vec<Vec<u8>> = init_vs
-            .active_validators(None)
-            .iter()
-            .map(|(validator_id, stake)| {
-                ValidatorUpdate {
-                    pub_key: Some(PublicKey {
-                        sum: Some(tendermint_proto::crypto::public_key::Sum::Ed25519(
-                            self.validator_id_to_pubkey_map.get(validator_id).unwrap(),
-                        )),
-                    }),
-                    power: (stake.0 / NEAR_SCALE) as i64,
-                }
-                .encode_to_vec()
-            })
-            .collect();
+        assert!(
+            !self.is_held_back(&init_vs),
+            "The validator set 0 has too few validators."
+        );
+        let init_powers = self
+            .get_target_voting_powers_of(&init_vs)
+            .unwrap_or_else(|err| panic!("{}", err));
+        self.applied_voting_powers.clear();
+        for (public_key, power) in &init_powers {
+            self.applied_voting_powers.insert(public_key, power);
+        }
+        let validators_bytes = encode_validators(&init_powers);
         log!("Validators bytes: {:?}", validators_bytes);
         let consensus_state = TmConsensusState {
             timestamp: TmTime::from_unix_timestamp(
@@ -121,6 +118,25 @@ impl AppchainLifecycleManager for AppchainAnchor {
     }
 }
 
+/// Encode the validators in the same way as `SimpleValidator` of CometBFT,
+/// which is used for calculating the hash of validator set.
+fn encode_validators(powers: &[(Vec<u8>, u64)]) -> Vec<Vec<u8>> {
+    powers
+        .iter()
+        .map(|(public_key, power)| {
+            ValidatorUpdate {
+                pub_key: Some(PublicKey {
+                    sum: Some(tendermint_proto::crypto::public_key::Sum::Ed25519(
+                        public_key.clone(),
+                    )),
+                }),
+                power: i64::try_from(*power).expect("Voting power overflow, should not happen."),
+            }
+            .encode_to_vec()
+        })
+        .collect()
+}
+
 fn merkle_hash(bytes_array: &Vec<Vec<u8>>) -> Vec<u8> {
     match bytes_array.len() {
         0 => empty_hash(),
@@ -152,3 +168,43 @@ fn inner_hash(left: &[u8], right: &[u8]) -> Vec<u8> {
     inner_bytes.extend_from_slice(right);
     env::sha256(inner_bytes.as_slice())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voting_power::sort_voting_powers;
+    use near_sdk::{test_utils::VMContextBuilder, testing_env};
+    use tendermint::{validator, vote};
+
+    #[test]
+    fn test_validator_set_hash() {
+        let context = VMContextBuilder::new().build();
+        testing_env!(context);
+        let mut powers: Vec<(Vec<u8>, u64)> = vec![
+            "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+            "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+            "fc51cd8e6218a1a38da47ed00230f0580816ed13ba3303ac5deb911548908025",
+            "278117fc144c72340f67d0f2316e8386ceffbf2b2428c9c51fef7c597f1d426e",
+        ]
+        .into_iter()
+        .zip([100, 300, 100, 200])
+        .map(|(key, power)| (hex::decode(key).unwrap(), power))
+        .collect();
+        let tm_validator_set = validator::Set::without_proposer(
+            powers
+                .iter()
+                .map(|(public_key, power)| {
+                    validator::Info::new(
+                        tendermint::PublicKey::from_raw_ed25519(public_key).unwrap(),
+                        vote::Power::try_from(*power).unwrap(),
+                    )
+                })
+                .collect(),
+        );
+        sort_voting_powers(&mut powers);
+        assert_eq!(
+            merkle_hash(&encode_validators(&powers)),
+            tm_validator_set.hash().as_bytes().to_vec()
+        );
+    }
+}
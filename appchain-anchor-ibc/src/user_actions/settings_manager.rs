@@ -1,3 +1,4 @@
+use crate::voting_power::VOTING_POWER_SHARE_DENOMINATOR;
 use crate::*;
 use core::convert::From;
 
@@ -20,6 +21,30 @@ pub trait AnchorSettingsManager {
     fn change_min_unjail_interval(&mut self, interval_secs: U64);
     ///
     fn change_appchain_address_bech32_hrp(&mut self, bech32_hrp: String);
+    ///
+    fn change_slash_request_veto_window(&mut self, window_secs: U64);
+    ///
+    fn change_auto_unjail_enabled(&mut self, value: bool);
+    ///
+    fn change_permissionless_unjail_enabled(&mut self, value: bool);
+    ///
+    fn change_jail_escalation_window(&mut self, window_secs: U64);
+    ///
+    fn change_max_unjail_interval(&mut self, interval_secs: U64);
+    ///
+    fn change_max_voting_power_share(&mut self, share_in_bps: u16);
+    ///
+    fn change_max_voting_power_change_share(&mut self, share_in_bps: u16);
+    ///
+    fn change_voting_power_divisor(&mut self, divisor: U128);
+    ///
+    fn change_min_count_of_validators(&mut self, value: u32);
+    ///
+    fn change_vsc_packet_timeout_policy(&mut self, policy: VscPacketTimeoutPolicy);
+    ///
+    fn change_min_interval_for_same_vsc_packet(&mut self, interval_secs: U64);
+    ///
+    fn change_max_validator_updates_per_vsc_packet(&mut self, value: u32);
 }
 
 impl Default for AnchorSettings {
@@ -34,6 +59,18 @@ impl Default for AnchorSettings {
             min_validator_staking_amount: U128::from(10_000_000_000_000_000_000_000_000_000),
             min_unjail_interval: U64::from(600 * 1_000_000_000),
             appchain_address_bech32_hrp: "unknown".to_string(),
+            slash_request_veto_window: U64::from(86400 * 1_000_000_000),
+            auto_unjail_enabled: false,
+            permissionless_unjail_enabled: false,
+            jail_escalation_window: U64::from(0),
+            max_unjail_interval: U64::from(604800 * 1_000_000_000),
+            max_voting_power_share: 0,
+            max_voting_power_change_share: 0,
+            voting_power_divisor: U128::from(NEAR_SCALE),
+            min_count_of_validators: 1,
+            vsc_packet_timeout_policy: VscPacketTimeoutPolicy::CloseAppchain,
+            min_interval_for_same_vsc_packet: U64::from(600 * 1_000_000_000),
+            max_validator_updates_per_vsc_packet: 0,
         }
     }
 }
@@ -143,4 +180,160 @@ impl AnchorSettingsManager for AppchainAnchor {
         anchor_settings.appchain_address_bech32_hrp = bech32_hrp;
         self.anchor_settings.set(&anchor_settings);
     }
+    //
+    fn change_slash_request_veto_window(&mut self, window_secs: U64) {
+        self.assert_owner();
+        let mut anchor_settings = self.anchor_settings.get().unwrap();
+        let window = window_secs.0 * 1_000_000_000;
+        assert!(
+            window != anchor_settings.slash_request_veto_window.0,
+            "The value is not changed."
+        );
+        anchor_settings.slash_request_veto_window = U64::from(window);
+        self.anchor_settings.set(&anchor_settings);
+    }
+    //
+    fn change_auto_unjail_enabled(&mut self, value: bool) {
+        self.assert_owner();
+        let mut anchor_settings = self.anchor_settings.get().unwrap();
+        assert!(
+            value != anchor_settings.auto_unjail_enabled,
+            "The value is not changed."
+        );
+        anchor_settings.auto_unjail_enabled = value;
+        self.anchor_settings.set(&anchor_settings);
+    }
+    //
+    fn change_permissionless_unjail_enabled(&mut self, value: bool) {
+        self.assert_owner();
+        let mut anchor_settings = self.anchor_settings.get().unwrap();
+        assert!(
+            value != anchor_settings.permissionless_unjail_enabled,
+            "The value is not changed."
+        );
+        anchor_settings.permissionless_unjail_enabled = value;
+        self.anchor_settings.set(&anchor_settings);
+    }
+    //
+    fn change_jail_escalation_window(&mut self, window_secs: U64) {
+        self.assert_owner();
+        let mut anchor_settings = self.anchor_settings.get().unwrap();
+        let window = window_secs.0 * 1_000_000_000;
+        assert!(
+            window != anchor_settings.jail_escalation_window.0,
+            "The value is not changed."
+        );
+        anchor_settings.jail_escalation_window = U64::from(window);
+        self.anchor_settings.set(&anchor_settings);
+    }
+    //
+    fn change_max_unjail_interval(&mut self, interval_secs: U64) {
+        self.assert_owner();
+        let mut anchor_settings = self.anchor_settings.get().unwrap();
+        let interval = interval_secs.0 * 1_000_000_000;
+        assert!(
+            interval != anchor_settings.max_unjail_interval.0,
+            "The value is not changed."
+        );
+        assert!(
+            interval >= anchor_settings.min_unjail_interval.0,
+            "The value must not be less than 'min_unjail_interval'."
+        );
+        anchor_settings.max_unjail_interval = U64::from(interval);
+        self.anchor_settings.set(&anchor_settings);
+    }
+    //
+    fn change_max_voting_power_share(&mut self, share_in_bps: u16) {
+        self.assert_owner();
+        let mut anchor_settings = self.anchor_settings.get().unwrap();
+        assert!(
+            share_in_bps != anchor_settings.max_voting_power_share,
+            "The value is not changed."
+        );
+        assert!(
+            share_in_bps <= VOTING_POWER_SHARE_DENOMINATOR,
+            "The value must not be greater than {}.",
+            VOTING_POWER_SHARE_DENOMINATOR
+        );
+        anchor_settings.max_voting_power_share = share_in_bps;
+        self.anchor_settings.set(&anchor_settings);
+    }
+    //
+    fn change_max_voting_power_change_share(&mut self, share_in_bps: u16) {
+        self.assert_owner();
+        let mut anchor_settings = self.anchor_settings.get().unwrap();
+        assert!(
+            share_in_bps != anchor_settings.max_voting_power_change_share,
+            "The value is not changed."
+        );
+        assert!(
+            share_in_bps <= VOTING_POWER_SHARE_DENOMINATOR,
+            "The value must not be greater than {}.",
+            VOTING_POWER_SHARE_DENOMINATOR
+        );
+        anchor_settings.max_voting_power_change_share = share_in_bps;
+        self.anchor_settings.set(&anchor_settings);
+    }
+    //
+    fn change_voting_power_divisor(&mut self, divisor: U128) {
+        self.assert_owner();
+        let mut anchor_settings = self.anchor_settings.get().unwrap();
+        assert!(
+            divisor.0 != anchor_settings.voting_power_divisor.0,
+            "The value is not changed."
+        );
+        assert!(divisor.0 > 0, "The value must be greater than 0.");
+        anchor_settings.voting_power_divisor = divisor;
+        self.anchor_settings.set(&anchor_settings);
+    }
+    //
+    fn change_min_count_of_validators(&mut self, value: u32) {
+        self.assert_owner();
+        let mut anchor_settings = self.anchor_settings.get().unwrap();
+        assert!(value > 0, "The value should be greater than 0.");
+        assert!(
+            value <= anchor_settings.max_count_of_validators,
+            "The value should not be greater than the maximum validator count."
+        );
+        assert!(
+            value != anchor_settings.min_count_of_validators,
+            "The value is not changed."
+        );
+        anchor_settings.min_count_of_validators = value;
+        self.anchor_settings.set(&anchor_settings);
+    }
+    //
+    fn change_vsc_packet_timeout_policy(&mut self, policy: VscPacketTimeoutPolicy) {
+        self.assert_owner();
+        let mut anchor_settings = self.anchor_settings.get().unwrap();
+        assert!(
+            policy != anchor_settings.vsc_packet_timeout_policy,
+            "The value is not changed."
+        );
+        anchor_settings.vsc_packet_timeout_policy = policy;
+        self.anchor_settings.set(&anchor_settings);
+    }
+    //
+    fn change_min_interval_for_same_vsc_packet(&mut self, interval_secs: U64) {
+        self.assert_owner();
+        let mut anchor_settings = self.anchor_settings.get().unwrap();
+        let interval = interval_secs.0 * 1_000_000_000;
+        assert!(
+            interval != anchor_settings.min_interval_for_same_vsc_packet.0,
+            "The value is not changed."
+        );
+        anchor_settings.min_interval_for_same_vsc_packet = U64::from(interval);
+        self.anchor_settings.set(&anchor_settings);
+    }
+    //
+    fn change_max_validator_updates_per_vsc_packet(&mut self, value: u32) {
+        self.assert_owner();
+        let mut anchor_settings = self.anchor_settings.get().unwrap();
+        assert!(
+            value != anchor_settings.max_validator_updates_per_vsc_packet,
+            "The value is not changed."
+        );
+        anchor_settings.max_validator_updates_per_vsc_packet = value;
+        self.anchor_settings.set(&anchor_settings);
+    }
 }
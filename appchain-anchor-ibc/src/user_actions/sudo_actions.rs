@@ -1,4 +1,11 @@
-use crate::{contract_actions::reward_token_callbacks::ext_reward_token_callbacks, *};
+use crate::{
+    contract_actions::{
+        restaking_base_callbacks::ext_restaking_base_callbacks,
+        reward_token_callbacks::ext_reward_token_callbacks,
+    },
+    ext_contracts::ext_restaking_base,
+    *,
+};
 use near_contract_standards::fungible_token::core::ext_ft_core;
 use near_sdk::IntoStorageKey;
 
@@ -25,8 +32,25 @@ pub trait SudoActions {
     fn force_jail_validator(&mut self, validator_id: AccountId);
     ///
     fn checked_clean_distributed_rewards(&mut self);
+    /// Clear the jail records of all validators in the latest validator set.
+    fn clear_jailed_validators(&mut self) -> ProcessingResult;
     ///
-    fn clear_jailed_validators(&mut self);
+    fn approve_slash_request(&mut self, slash_id: U64);
+    /// Veto a slash request within `slash_request_veto_window`, and cancel it
+    /// in restaking base contract.
+    fn reject_slash_request(&mut self, slash_id: U64);
+    ///
+    fn replay_dead_letter_slash_packet(&mut self, index: U64);
+    ///
+    fn remove_dead_letter_slash_packet(&mut self, index: U64);
+    ///
+    fn remove_slash_histories_before(&mut self, index: U64) -> ProcessingResult;
+    /// Release the lock of fetching validator set from restaking base contract,
+    /// in case the callback of the fetching is not executed.
+    fn release_validator_set_fetch_lock(&mut self);
+    /// Discard the validator set which is being built. It will be cleared
+    /// by `continue_building_validator_set`.
+    fn discard_building_validator_set(&mut self);
 }
 
 #[near_bindgen]
@@ -61,6 +85,7 @@ impl SudoActions for AppchainAnchor {
     //
     fn remove_oldest_validator_set(&mut self) -> String {
         self.assert_owner();
+        self.assert_state_migrated();
         let anchor_settings = self.anchor_settings.get().unwrap();
         assert!(
             self.validator_set_histories.len()
@@ -86,6 +111,7 @@ impl SudoActions for AppchainAnchor {
                 RemovingValidatorSetSteps::ClearingOldestValidatorSet => {
                     let result = self.validator_set_histories.remove_first(max_gas);
                     if result.is_ok() {
+                        self.remove_vsc_packet_records_of(&era_number);
                         RemovingValidatorSetSteps::clear();
                         (result, None)
                     } else {
@@ -124,7 +150,6 @@ impl SudoActions for AppchainAnchor {
         if let Some(validator_set) = self.validator_set_histories.get_last() {
             self.send_vsc_packet(
                 &validator_set,
-                &self.validator_set_histories.get_second_last(),
                 removing_pubkeys
                     .iter()
                     .map(|rp| {
@@ -139,15 +164,16 @@ impl SudoActions for AppchainAnchor {
     //
     fn remove_first_pending_slash_packets(&mut self) {
         self.assert_owner();
+        self.assert_state_migrated();
         let max_gas = Gas::from_tgas(20);
-        let packet_string = self
+        let slash_packet = self
             .pending_slash_packets
             .get_first()
             .expect("No pending slash packet found.");
         self.pending_slash_packets.remove_first(max_gas);
         log!(
-            "The first pending slash packet has been removed: {}",
-            packet_string
+            "The first pending slash packet has been removed: {:?}",
+            slash_packet
         );
     }
     //
@@ -161,8 +187,8 @@ impl SudoActions for AppchainAnchor {
             validator_set.contains_validator(&validator_id),
             "No validator found."
         );
-        validator_set.jail_validator(&validator_id);
-        self.validator_set_histories.update_last(&validator_set);
+        validator_set.jail_validator(&validator_id, &mut self.jail_ledger);
+        self.update_latest_validator_set(&validator_set, &[validator_id.clone()]);
         log!("The validator '{}' has been jailed.", validator_id);
     }
     //
@@ -192,13 +218,144 @@ impl SudoActions for AppchainAnchor {
         }
     }
     //
-    fn clear_jailed_validators(&mut self) {
+    fn clear_jailed_validators(&mut self) -> ProcessingResult {
         self.assert_owner();
-        let mut validator_set = self
+        let validator_set = self
             .validator_set_histories
             .get_last()
             .expect("No validator set found.");
-        validator_set.clear_jailed_validators();
-        self.validator_set_histories.update_last(&validator_set);
+        let max_gas = Gas::from_tgas(T_GAS_CAP_FOR_MULTI_TXS_PROCESSING);
+        for validator_id in validator_set.get_validator_ids() {
+            let result = self.jail_ledger.clear_records_of(&validator_id, max_gas);
+            if !result.is_ok() {
+                return result;
+            }
+        }
+        ProcessingResult::Ok
+    }
+    //
+    fn approve_slash_request(&mut self, slash_id: U64) {
+        self.assert_owner();
+        let slash_request = self
+            .slash_requests
+            .get(&slash_id.0)
+            .expect("Slash request not found.");
+        assert_eq!(
+            slash_request.state,
+            SlashRequestState::Requested,
+            "Slash request is not in 'Requested' state."
+        );
+        self.update_slash_request_state(&slash_id, SlashRequestState::Approved);
+    }
+    //
+    fn reject_slash_request(&mut self, slash_id: U64) {
+        self.assert_owner();
+        let slash_request = self
+            .slash_requests
+            .get(&slash_id.0)
+            .expect("Slash request not found.");
+        assert_eq!(
+            slash_request.state,
+            SlashRequestState::Requested,
+            "Slash request is not in 'Requested' state."
+        );
+        let anchor_settings = self.anchor_settings.get().unwrap();
+        assert!(
+            env::block_timestamp()
+                <= slash_request.requested_timestamp + anchor_settings.slash_request_veto_window.0,
+            "The veto window of slash request {} has passed.",
+            slash_id.0
+        );
+        self.update_slash_request_state(&slash_id, SlashRequestState::Rejected);
+        ext_restaking_base::ext(self.restaking_base_contract.clone())
+            .cancel_slash_request(slash_id)
+            .then(
+                ext_restaking_base_callbacks::ext(env::current_account_id())
+                    .cancel_slash_request_callback(slash_id),
+            );
+    }
+    //
+    fn replay_dead_letter_slash_packet(&mut self, index: U64) {
+        self.assert_owner();
+        let mut dead_letter = self
+            .dead_letter_slash_packets
+            .get(&index.0)
+            .expect("Dead-letter slash packet not found.");
+        self.dead_letter_slash_packets
+            .remove_at(&index.0, Gas::from_tgas(T_GAS_FOR_SIMPLE_FUNCTION_CALL));
+        self.pending_slash_packets
+            .append(&mut dead_letter.slash_packet);
+        log!(
+            "The dead-letter slash packet {} has been moved back to pending slash packets: {:?}",
+            index.0,
+            dead_letter.slash_packet
+        );
+    }
+    //
+    fn remove_dead_letter_slash_packet(&mut self, index: U64) {
+        self.assert_owner();
+        let dead_letter = self
+            .dead_letter_slash_packets
+            .get(&index.0)
+            .expect("Dead-letter slash packet not found.");
+        self.dead_letter_slash_packets
+            .remove_at(&index.0, Gas::from_tgas(T_GAS_FOR_SIMPLE_FUNCTION_CALL));
+        log!(
+            "The dead-letter slash packet {} has been removed: {:?}",
+            index.0,
+            dead_letter.slash_packet
+        );
+    }
+    //
+    fn remove_slash_histories_before(&mut self, index: U64) -> ProcessingResult {
+        self.assert_owner();
+        let max_gas = Gas::from_tgas(T_GAS_CAP_FOR_MULTI_TXS_PROCESSING);
+        while let Some(record) = self.slash_histories.get_first() {
+            if record.index.0 >= index.0 {
+                break;
+            }
+            if env::used_gas() > max_gas {
+                return ProcessingResult::NeedMoreGas;
+            }
+            if let Some(validator_id) = record.validator_id {
+                let mut indexes = self
+                    .slash_history_indexes_of_validator
+                    .get(&validator_id)
+                    .unwrap_or_default();
+                indexes.retain(|i| *i != record.index.0);
+                if indexes.is_empty() {
+                    self.slash_history_indexes_of_validator
+                        .remove(&validator_id);
+                } else {
+                    self.slash_history_indexes_of_validator
+                        .insert(&validator_id, &indexes);
+                }
+            }
+            self.slash_histories.remove_first(max_gas);
+        }
+        ProcessingResult::Ok
+    }
+    //
+    fn release_validator_set_fetch_lock(&mut self) {
+        self.assert_owner();
+        assert!(
+            self.validator_set_fetch_in_flight,
+            "Fetching validator set is not in progress."
+        );
+        self.validator_set_fetch_in_flight = false;
+    }
+    //
+    fn discard_building_validator_set(&mut self) {
+        self.assert_owner();
+        let mut builder = self
+            .validator_set_builder
+            .get()
+            .expect("No validator set is being built.");
+        assert!(
+            builder.rejection().is_none(),
+            "The validator set is already discarded."
+        );
+        builder.reject("Discarded by owner.".to_string());
+        self.validator_set_builder.set(&builder);
     }
 }
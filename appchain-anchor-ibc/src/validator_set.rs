@@ -15,7 +15,8 @@ pub enum ValidatorStatus {
     /// A validator with this status will not be sent to appchain in the next VSC packet.
     ///
     /// A jailed validator can call the `unjail_validator` function to unjail itself (to change
-    /// its status to `Active`).
+    /// its status to `Active`). If auto unjail is enabled in anchor settings, the validator will
+    /// be unjailed automatically in the first new validator set after `min_unjail_interval`.
     Jailed,
     /// The validator is waiting for slash by appchain governance.
     ///
@@ -69,8 +70,26 @@ pub struct ValidatorSet {
     pub timestamp: Timestamp,
     /// Whether the validator set is matured in the corresponding appchain.
     pub matured_in_appchain: bool,
-    /// The jailed validators with their account id, jailed time and unjailed time.
-    pub jailed_validators: Vec<(AccountId, Timestamp, Timestamp)>,
+    /// The timestamp of when the validator set is matured in the corresponding appchain,
+    /// if it is known.
+    pub matured_timestamp: Option<Timestamp>,
+}
+
+/// A validator set which is being built from the validator set in restaking base contract,
+/// across multiple transactions.
+#[derive(BorshDeserialize, BorshSerialize)]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct ValidatorSetBuilder {
+    /// The validators and their stakes in restaking base contract.
+    validators: Vec<(AccountId, Balance)>,
+    /// The validator set being built.
+    validator_set: ValidatorSet,
+    /// The count of validators which are already added to the validator set.
+    added_count: u64,
+    /// The validators which are unjailed automatically in the validator set.
+    auto_unjailed_validators: Vec<AccountId>,
+    /// The reason of rejection, if the validator set is rejected and being cleared.
+    rejection: Option<String>,
 }
 
 pub trait ValidatorSetViewer {
@@ -91,75 +110,48 @@ pub trait ValidatorSetViewer {
     ///
     fn matured_in_appchain(&self) -> bool;
     ///
+    fn matured_timestamp(&self) -> Option<Timestamp>;
+    ///
     fn total_stake(&self) -> u128;
     ///
     fn validator_count(&self) -> u64;
     ///
-    fn active_validators(&self, period: Option<(Timestamp, Timestamp)>) -> Vec<(AccountId, U128)>;
-    ///
-    fn jailed_validators(&self) -> Vec<(AccountId, Timestamp, Timestamp)>;
+    fn active_validators(&self) -> Vec<(AccountId, U128)>;
     ///
     fn slash_ack_validators(&self) -> Vec<AccountId>;
 }
 
 impl ValidatorSet {
-    ///
-    pub fn new(
-        last_vs: &Option<ValidatorSet>,
-        restaking_base_vs: &RestakingBaseValidatorSet,
-        min_validator_staking_amount: u128,
-    ) -> Self {
-        if let Some(last_vs) = last_vs {
-            let id = last_vs.id + 1;
-            let mut jailed_validators = Vec::new();
-            for (id, jt, ut) in last_vs.jailed_validators.iter() {
-                if *ut == 0 {
-                    jailed_validators.push((id.clone(), *jt, *ut));
-                }
-            }
-            let mut new_instance = Self {
-                id,
-                validator_id_set: UnorderedSet::new(
-                    StorageKey::ValidatorIdSetOf(id).into_storage_key(),
-                ),
-                validators: LookupMap::new(StorageKey::ValidatorsOf(id).into_storage_key()),
-                total_stake: 0,
-                sequence: restaking_base_vs.sequence.0,
-                timestamp: env::block_timestamp(),
-                matured_in_appchain: false,
-                jailed_validators,
-            };
-            for (validator_id, stake) in &restaking_base_vs.validator_set {
-                new_instance.add_validator(
-                    validator_id.clone(),
-                    stake.0,
-                    if stake.0 >= min_validator_staking_amount {
-                        if let Some(validator) = last_vs.get_validator(&validator_id) {
-                            validator.status.clone()
-                        } else {
-                            ValidatorStatus::Active
-                        }
-                    } else {
-                        ValidatorStatus::Unqualified
-                    },
-                );
-            }
-            new_instance
-        } else {
-            Self {
-                id: 0,
-                validator_id_set: UnorderedSet::new(
-                    StorageKey::ValidatorIdSetOf(0).into_storage_key(),
-                ),
-                validators: LookupMap::new(StorageKey::ValidatorsOf(0).into_storage_key()),
-                total_stake: 0,
-                sequence: restaking_base_vs.sequence.0,
-                timestamp: env::block_timestamp(),
-                matured_in_appchain: false,
-                jailed_validators: Vec::new(),
-            }
+    /// Create an empty validator set following the last validator set, for the validator set
+    /// with the given sequence in restaking base contract.
+    pub fn new(last_vs: &Option<ValidatorSet>, sequence: u64) -> Self {
+        let id = last_vs.as_ref().map_or(0, |last_vs| last_vs.id + 1);
+        Self {
+            id,
+            validator_id_set: UnorderedSet::new(
+                StorageKey::ValidatorIdSetOf(id).into_storage_key(),
+            ),
+            validators: LookupMap::new(StorageKey::ValidatorsOf(id).into_storage_key()),
+            total_stake: 0,
+            sequence,
+            timestamp: env::block_timestamp(),
+            matured_in_appchain: false,
+            matured_timestamp: None,
         }
     }
+    /// Whether the validators and their stakes in this set are the same as
+    /// the given validator set in restaking base contract.
+    pub fn has_same_validators_as(&self, restaking_base_vs: &RestakingBaseValidatorSet) -> bool {
+        self.validator_id_set.len() == restaking_base_vs.validator_set.len() as u64
+            && restaking_base_vs
+                .validator_set
+                .iter()
+                .all(|(validator_id, stake)| {
+                    self.validators
+                        .get(validator_id)
+                        .is_some_and(|validator| validator.total_stake == stake.0)
+                })
+    }
     ///
     pub fn add_validator(
         &mut self,
@@ -185,7 +177,7 @@ impl ValidatorSet {
         }
     }
     ///
-    pub fn jail_validator(&mut self, validator_id: &AccountId) {
+    pub fn jail_validator(&mut self, validator_id: &AccountId, jail_ledger: &mut JailLedger) {
         if let Some(validator) = self.validators.get(validator_id) {
             if validator.status == ValidatorStatus::Active {
                 self.validators.insert(
@@ -196,7 +188,7 @@ impl ValidatorSet {
                         status: ValidatorStatus::Jailed,
                     },
                 );
-                self.update_jailed_timestamp(validator_id);
+                jail_ledger.jail(validator_id);
             } else {
                 panic!("Validator is not active: {}", validator_id)
             }
@@ -205,7 +197,12 @@ impl ValidatorSet {
         }
     }
     ///
-    pub fn unjail_validator(&mut self, validator_id: &AccountId, min_unjail_interval: u64) {
+    pub fn unjail_validator(
+        &mut self,
+        validator_id: &AccountId,
+        anchor_settings: &AnchorSettings,
+        jail_ledger: &mut JailLedger,
+    ) {
         if let Some(validator) = self.validators.get(validator_id) {
             if validator.status == ValidatorStatus::Jailed {
                 self.validators.insert(
@@ -216,7 +213,7 @@ impl ValidatorSet {
                         status: ValidatorStatus::Active,
                     },
                 );
-                self.update_unjailed_timestamp(validator_id, min_unjail_interval);
+                jail_ledger.unjail(validator_id, anchor_settings);
             } else {
                 panic!("Validator is not jailed: {}", validator_id)
             }
@@ -227,7 +224,9 @@ impl ValidatorSet {
     ///
     pub fn wait_for_slashing_validator(&mut self, validator_id: &AccountId, slash_id: U64) {
         if let Some(validator) = self.validators.get(validator_id) {
-            if validator.status == ValidatorStatus::Active {
+            if validator.status == ValidatorStatus::Active
+                || validator.status == ValidatorStatus::Jailed
+            {
                 self.validators.insert(
                     &validator_id,
                     &Validator {
@@ -240,6 +239,27 @@ impl ValidatorSet {
         }
     }
     ///
+    pub fn confirm_slashing_validator(&mut self, validator_id: &AccountId, slash_id: &U64) {
+        self.change_status_of_slashing_validator(validator_id, slash_id, ValidatorStatus::Slashed);
+    }
+    /// Restore the status of a validator waiting for the given slash request, which is
+    /// `Jailed` if the validator still has an open jail record, otherwise `Active`.
+    pub fn cancel_slashing_validator(
+        &mut self,
+        validator_id: &AccountId,
+        slash_id: &U64,
+        jail_ledger: &JailLedger,
+    ) {
+        self.change_status_of_slashing_validator(
+            validator_id,
+            slash_id,
+            match jail_ledger.get_open_record(validator_id) {
+                Some(_) => ValidatorStatus::Jailed,
+                None => ValidatorStatus::Active,
+            },
+        );
+    }
+    ///
     pub fn clear(&mut self, max_gas: Gas) -> ProcessingResult {
         let validator_ids = self.validator_id_set.to_vec();
         for validator_id in validator_ids {
@@ -255,10 +275,122 @@ impl ValidatorSet {
     ///
     pub fn set_matured(&mut self) {
         self.matured_in_appchain = true;
+        self.matured_timestamp = Some(env::block_timestamp());
+    }
+}
+
+impl ValidatorSetBuilder {
+    ///
+    pub fn new(
+        last_vs: &Option<ValidatorSet>,
+        restaking_base_vs: &RestakingBaseValidatorSet,
+    ) -> Self {
+        Self {
+            validators: restaking_base_vs
+                .validator_set
+                .iter()
+                .map(|(validator_id, stake)| (validator_id.clone(), stake.0))
+                .collect(),
+            validator_set: ValidatorSet::new(last_vs, restaking_base_vs.sequence.0),
+            added_count: 0,
+            auto_unjailed_validators: Vec::new(),
+            rejection: None,
+        }
+    }
+    ///
+    pub fn validator_set(&self) -> &ValidatorSet {
+        &self.validator_set
+    }
+    ///
+    pub fn added_count(&self) -> u64 {
+        self.added_count
+    }
+    ///
+    pub fn total_count(&self) -> u64 {
+        self.validators.len() as u64
+    }
+    /// Add the remaining validators to the validator set, until all of them are added
+    /// or the used gas exceeds `max_gas`.
+    ///
+    /// The status of a qualified validator is inherited from the last validator set.
+    /// A jailed validator is unjailed automatically if it is allowed by anchor settings,
+    /// and the unjailing should be recorded in jail ledger once the validator set is accepted.
+    pub fn build(
+        &mut self,
+        last_vs: &Option<ValidatorSet>,
+        anchor_settings: &AnchorSettings,
+        jail_ledger: &JailLedger,
+        max_gas: Gas,
+    ) -> ProcessingResult {
+        while self.added_count < self.total_count() {
+            if env::used_gas() > max_gas {
+                return ProcessingResult::NeedMoreGas;
+            }
+            let (validator_id, stake) = self.validators[self.added_count as usize].clone();
+            let status = if stake < anchor_settings.min_validator_staking_amount.0 {
+                ValidatorStatus::Unqualified
+            } else {
+                match last_vs
+                    .as_ref()
+                    .and_then(|last_vs| last_vs.get_validator(&validator_id))
+                {
+                    Some(validator)
+                        if validator.status == ValidatorStatus::Jailed
+                            && anchor_settings.auto_unjail_enabled
+                            && jail_ledger.is_unjailable(&validator_id, anchor_settings) =>
+                    {
+                        self.auto_unjailed_validators.push(validator_id.clone());
+                        ValidatorStatus::Active
+                    }
+                    Some(validator) => validator.status,
+                    None => ValidatorStatus::Active,
+                }
+            };
+            self.validator_set
+                .add_validator(validator_id, stake, status);
+            self.added_count += 1;
+        }
+        ProcessingResult::Ok
+    }
+    /// Apply the status of a validator in the latest validator set to the validator set
+    /// being built, if the validator is already added to it as a qualified validator.
+    ///
+    /// The status of a validator in the latest validator set may be changed (by slashing or
+    /// unjailing) after it is inherited by the builder, so this should be called for every
+    /// such change before the validator set is completed.
+    pub fn sync_validator_status(&mut self, latest_validator: &Validator) {
+        let validator_id = &latest_validator.validator_id;
+        if let Some(validator) = self.validator_set.validators.get(validator_id) {
+            if validator.status == ValidatorStatus::Unqualified {
+                return;
+            }
+            self.auto_unjailed_validators
+                .retain(|id| id != validator_id);
+            self.validator_set.validators.insert(
+                validator_id,
+                &Validator {
+                    validator_id: validator_id.clone(),
+                    total_stake: validator.total_stake,
+                    status: latest_validator.status.clone(),
+                },
+            );
+        }
     }
     ///
-    pub fn clear_jailed_validators(&mut self) {
-        self.jailed_validators.clear();
+    pub fn rejection(&self) -> Option<String> {
+        self.rejection.clone()
+    }
+    /// Reject the validator set, it should be cleared by `clear` then.
+    pub fn reject(&mut self, reason: String) {
+        self.rejection = Some(reason);
+    }
+    /// Remove the validators which are already added to the validator set.
+    pub fn clear(&mut self, max_gas: Gas) -> ProcessingResult {
+        self.validator_set.clear(max_gas)
+    }
+    /// Get the validator set and the validators which are unjailed automatically in it.
+    pub fn into_parts(self) -> (ValidatorSet, Vec<AccountId>) {
+        (self.validator_set, self.auto_unjailed_validators)
     }
 }
 
@@ -299,6 +431,10 @@ impl ValidatorSetViewer for ValidatorSet {
         self.matured_in_appchain
     }
     //
+    fn matured_timestamp(&self) -> Option<Timestamp> {
+        self.matured_timestamp
+    }
+    //
     fn total_stake(&self) -> u128 {
         self.total_stake
     }
@@ -307,13 +443,13 @@ impl ValidatorSetViewer for ValidatorSet {
         self.validator_id_set.len()
     }
     //
-    fn active_validators(&self, period: Option<(Timestamp, Timestamp)>) -> Vec<(AccountId, U128)> {
-        self.validator_id_set
+    fn active_validators(&self) -> Vec<(AccountId, U128)> {
+        let mut active_validators: Vec<(AccountId, U128)> = self
+            .validator_id_set
             .iter()
             .filter(|id| {
                 if let Some(validator) = self.validators.get(&id) {
                     validator.status == ValidatorStatus::Active
-                        && !self.is_validator_jailed_in_period(id, period)
                 } else {
                     false
                 }
@@ -328,11 +464,11 @@ impl ValidatorSetViewer for ValidatorSet {
                     unreachable!()
                 }
             })
-            .collect()
-    }
-    //
-    fn jailed_validators(&self) -> Vec<(AccountId, Timestamp, Timestamp)> {
-        self.jailed_validators.clone()
+            .collect();
+        active_validators.sort_by(|(id_a, stake_a), (id_b, stake_b)| {
+            stake_b.0.cmp(&stake_a.0).then_with(|| id_a.cmp(id_b))
+        });
+        active_validators
     }
     //
     fn slash_ack_validators(&self) -> Vec<AccountId> {
@@ -365,52 +501,23 @@ impl IndexedAndClearable for ValidatorSet {
 
 impl ValidatorSet {
     //
-    fn update_jailed_timestamp(&mut self, validator_id: &AccountId) {
-        for (id, _, unjailed_time) in self.jailed_validators.iter() {
-            if id == validator_id && *unjailed_time == 0 {
-                panic!("Validator already jailed: {}", validator_id);
-            }
-        }
-        self.jailed_validators
-            .push((validator_id.clone(), env::block_timestamp(), 0));
-    }
-    //
-    fn update_unjailed_timestamp(&mut self, validator_id: &AccountId, min_unjail_interval: u64) {
-        for (id, jailed_time, unjailed_time) in self.jailed_validators.iter_mut() {
-            if id == validator_id && *unjailed_time == 0 {
-                if *jailed_time + min_unjail_interval > env::block_timestamp() {
-                    panic!("Validator is not jailed for long enough: {}", validator_id);
-                } else {
-                    *unjailed_time = env::block_timestamp();
-                }
-                return;
-            }
-        }
-        panic!("Validator not found: {}", validator_id);
-    }
-    //
-    fn is_validator_jailed_in_period(
-        &self,
+    fn change_status_of_slashing_validator(
+        &mut self,
         validator_id: &AccountId,
-        period: Option<(Timestamp, Timestamp)>,
-    ) -> bool {
-        if let Some((start, end)) = period {
-            for (id, jailed_time, unjailed_time) in self.jailed_validators.iter() {
-                if id == validator_id {
-                    if *unjailed_time == 0 {
-                        if *jailed_time >= start && *jailed_time <= end {
-                            return true;
-                        }
-                    } else {
-                        if *unjailed_time >= start && *unjailed_time <= end {
-                            return true;
-                        }
-                    }
-                }
+        slash_id: &U64,
+        status: ValidatorStatus,
+    ) {
+        if let Some(validator) = self.validators.get(validator_id) {
+            if validator.status == ValidatorStatus::WaitForSlash(*slash_id) {
+                self.validators.insert(
+                    &validator_id,
+                    &Validator {
+                        validator_id: validator_id.clone(),
+                        total_stake: validator.total_stake,
+                        status,
+                    },
+                );
             }
-            false
-        } else {
-            false
         }
     }
 }
@@ -0,0 +1,148 @@
+use crate::calculate_address;
+use core::cmp::Reverse;
+use near_contract_standards::fungible_token::Balance;
+
+/// The denominator of `max_voting_power_share` in anchor settings.
+pub const VOTING_POWER_SHARE_DENOMINATOR: u16 = 10_000;
+/// The maximum total voting power of a validator set in CometBFT.
+pub const MAX_TOTAL_VOTING_POWER: u64 = (i64::MAX / 8) as u64;
+
+/// Convert the stake of a validator to voting power, by the given divisor.
+///
+/// Returns an error if the divisor is zero, or the voting power exceeds
+/// `MAX_TOTAL_VOTING_POWER`.
+pub fn stake_to_voting_power(stake: Balance, divisor: u128) -> Result<u64, String> {
+    if divisor == 0 {
+        return Err("The voting power divisor must be greater than 0.".to_string());
+    }
+    u64::try_from(stake / divisor)
+        .ok()
+        .filter(|power| *power <= MAX_TOTAL_VOTING_POWER)
+        .ok_or(format!(
+            "The voting power of stake {} exceeds the maximum {}.",
+            stake, MAX_TOTAL_VOTING_POWER
+        ))
+}
+
+/// Convert the stakes of validators to voting powers, by the given divisor.
+///
+/// Returns an error if any conversion fails, or the total voting power exceeds
+/// `MAX_TOTAL_VOTING_POWER`.
+pub fn stakes_to_voting_powers(stakes: &[Balance], divisor: u128) -> Result<Vec<u64>, String> {
+    let powers = stakes
+        .iter()
+        .map(|stake| stake_to_voting_power(*stake, divisor))
+        .collect::<Result<Vec<u64>, String>>()?;
+    let total_power: u128 = powers.iter().map(|power| *power as u128).sum();
+    if total_power > MAX_TOTAL_VOTING_POWER as u128 {
+        return Err(format!(
+            "The total voting power {} exceeds the maximum {}.",
+            total_power, MAX_TOTAL_VOTING_POWER
+        ));
+    }
+    Ok(powers)
+}
+
+/// Cap the voting powers, so that no one exceeds `max_share` (in basis points)
+/// of the total voting power.
+///
+/// The excess of capped validators is redistributed to the others in proportion to
+/// their original powers, repeatedly, until no one exceeds the cap. If the cap can not be
+/// satisfied by the given count of validators, all of them will get the same power.
+///
+/// The powers are returned in the same order as the given ones.
+pub fn cap_voting_powers(powers: &[u64], max_share: u16) -> Vec<u64> {
+    if powers.is_empty() || max_share == 0 || max_share >= VOTING_POWER_SHARE_DENOMINATOR {
+        return powers.to_vec();
+    }
+    let total_power: u128 = powers.iter().map(|power| *power as u128).sum();
+    let count = powers.len() as u128;
+    if count * max_share as u128 <= VOTING_POWER_SHARE_DENOMINATOR as u128 {
+        return vec![(total_power / count) as u64; powers.len()];
+    }
+    let cap = total_power * max_share as u128 / VOTING_POWER_SHARE_DENOMINATOR as u128;
+    let mut capped = vec![false; powers.len()];
+    let mut result: Vec<u128> = powers.iter().map(|power| *power as u128).collect();
+    loop {
+        let capped_count = capped.iter().filter(|is_capped| **is_capped).count() as u128;
+        let remaining_power = total_power.saturating_sub(capped_count * cap);
+        let uncapped_power: u128 = powers
+            .iter()
+            .zip(capped.iter())
+            .filter(|(_, is_capped)| !**is_capped)
+            .map(|(power, _)| *power as u128)
+            .sum();
+        let mut changed = false;
+        for (index, power) in powers.iter().enumerate() {
+            if capped[index] {
+                result[index] = cap;
+                continue;
+            }
+            result[index] = match uncapped_power {
+                0 => 0,
+                _ => *power as u128 * remaining_power / uncapped_power,
+            };
+            if result[index] > cap {
+                capped[index] = true;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    result.into_iter().map(|power| power as u64).collect()
+}
+
+/// Sort the voting powers of validators in the canonical order of CometBFT,
+/// which is voting power descending, then address ascending.
+pub fn sort_voting_powers(powers: &mut [(Vec<u8>, u64)]) {
+    powers
+        .sort_by_cached_key(|(public_key, power)| (Reverse(*power), calculate_address(public_key)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cap_voting_powers() {
+        // No cap.
+        assert_eq!(cap_voting_powers(&[70, 20, 10], 0), vec![70, 20, 10]);
+        assert_eq!(cap_voting_powers(&[70, 20, 10], 10_000), vec![70, 20, 10]);
+        // The cap is not reached.
+        assert_eq!(cap_voting_powers(&[30, 30, 40], 5_000), vec![30, 30, 40]);
+        // The excess is redistributed in proportion to the other powers.
+        assert_eq!(cap_voting_powers(&[70, 20, 10], 5_000), vec![50, 33, 16]);
+        // The redistribution makes another validator exceed the cap.
+        assert_eq!(
+            cap_voting_powers(&[600, 300, 50, 50], 4_000),
+            vec![400, 400, 100, 100]
+        );
+        // The cap can not be satisfied.
+        assert_eq!(cap_voting_powers(&[90, 10], 3_333), vec![50, 50]);
+    }
+
+    #[test]
+    fn test_stakes_to_voting_powers() {
+        let near = 1_000_000_000_000_000_000_000_000;
+        assert_eq!(
+            stakes_to_voting_powers(&[near * 3 / 2, near / 2], near),
+            Ok(vec![1, 0])
+        );
+        assert_eq!(
+            stakes_to_voting_powers(&[near * 3 / 2, near / 2], near / 10),
+            Ok(vec![15, 5])
+        );
+        assert!(stakes_to_voting_powers(&[near], 0).is_err());
+        assert!(stake_to_voting_power(MAX_TOTAL_VOTING_POWER as u128 + 1, 1).is_err());
+        assert!(stakes_to_voting_powers(
+            &[
+                MAX_TOTAL_VOTING_POWER as u128,
+                MAX_TOTAL_VOTING_POWER as u128
+            ],
+            1
+        )
+        .is_err());
+    }
+}
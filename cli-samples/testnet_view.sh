@@ -26,7 +26,7 @@ export ANCHOR_ACCOUNT_ID=$APPCHAIN_ID'.'$REGISTRY_ACCOUNT_ID
 #
 # near view $ANCHOR_ACCOUNT_ID get_pending_rewards
 #
-# near view $ANCHOR_ACCOUNT_ID get_pending_slash_packets
+# near view $ANCHOR_ACCOUNT_ID get_pending_slash_packets '{"start_index":"0","quantity":"50"}'
 #
 # near view oct.beta_oct_relay.testnet ft_balance_of '{"account_id":"oct-cosmos-1.registry.test_oct.testnet"}'
 #